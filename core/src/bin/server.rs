@@ -0,0 +1,48 @@
+//! Headless server of a cooperative game on a shared infinite world.
+//! Players join it with `minesweeper join <address>`.
+
+use std::path::Path;
+
+use minesweeper_core::*;
+
+/// Where chunks out of every player's view are kept, one directory per world.
+const SAVE_DIR: &str = "./saves/server";
+
+fn main() {
+    // `[address] [seed]`, listening on 127.0.0.1:7777 by default with a seed from the clock.
+    // Other arguments set up the world as for a game played alone, e.g. `no-guess`, `lives <n>`, `lock <radius>`,
+    // `density <curve>`, `rule <neighbourhood>` or `hex`, boards aside.
+    let mut address = "127.0.0.1:7777".to_string();
+    let mut seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    let mut config = Config::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg.contains(':') {
+            address = arg;
+        } else if let Ok(s) = arg.parse() {
+            seed = s;
+        } else if let Err(e) = config.parse_arg(&arg, &mut args) {
+            println!("Ignoring {}", e);
+        }
+    }
+    if config.board.take().is_some() {
+        println!("Ignoring board: the server plays on the infinite world");
+    }
+    if let Err(e) = config.check() {
+        println!("Unable to play: {}", e);
+        return;
+    }
+    let mut server = match Server::bind(&address, seed, config, Path::new(SAVE_DIR)) {
+        Ok(server) => server,
+        Err(e) => {
+            println!("Unable to listen on {}: {}", address, e);
+            return;
+        },
+    };
+    if let Ok(address) = server.local_addr() {
+        println!("Listening on {}", address);
+    }
+    server.run();
+}
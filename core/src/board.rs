@@ -0,0 +1,131 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::*;
+
+/// Size and mine count of a finite board. Its cells go from (0, 0) to (width - 1, height - 1).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BoardSize {
+    pub width: u32,
+    pub height: u32,
+    pub mines: u32,
+}
+
+impl BoardSize {
+    pub const BEGINNER: BoardSize = BoardSize { width: 9, height: 9, mines: 10 };
+    pub const INTERMEDIATE: BoardSize = BoardSize { width: 16, height: 16, mines: 40 };
+    pub const EXPERT: BoardSize = BoardSize { width: 30, height: 16, mines: 99 };
+
+    /// Largest side of a custom board.
+    pub const MAX_SIDE: u32 = 1024;
+
+    /// A custom board, with at least one cell free of mines.
+    /// Room for a safe first click depends on the shape of the cells, see `Config::check`.
+    pub fn custom(width: u32, height: u32, mines: u32) -> Result<BoardSize, String> {
+        if width == 0 || height == 0 || width > Self::MAX_SIDE || height > Self::MAX_SIDE {
+            return Err(format!("board sides must be between 1 and {}", Self::MAX_SIDE));
+        }
+        if mines >= width * height {
+            return Err(format!("a {}x{} board holds at most {} mines", width, height, width * height - 1));
+        }
+        Ok(BoardSize { width, height, mines })
+    }
+
+    pub fn contains(&self, pos: WorldPos) -> bool {
+        pos.x >= 0 && pos.y >= 0 && (pos.x as u32) < self.width && (pos.y as u32) < self.height
+    }
+
+    /// Chunks overlapping the board.
+    pub fn chunks(&self) -> ChunkRect {
+        let last = WorldPos::new(self.width as i32 - 1, self.height as i32 - 1);
+        ChunkRect::new(ChunkPos::new(0, 0), last.chunk())
+    }
+
+    /// Unlike the infinite world, mines are spread over the whole board at once,
+    /// so the board has exactly `mines` of them. None is placed on the cells of `safe`.
+    /// Returns one entry per cell, row by row.
+    pub fn mine_layout(&self, seed: u64, safe: &[WorldPos]) -> Vec<bool> {
+        let len = (self.width * self.height) as usize;
+        let mut candidates: Vec<u32> = (0..len as u32)
+            .filter(|i| !safe.contains(&WorldPos::new((i % self.width) as i32, (i / self.width) as i32)))
+            .collect();
+        let mut rng = Rng::new(seed);
+        let mut result = vec![false; len];
+        let nb_mines = (self.mines as usize).min(candidates.len());
+        // Partial Fisher-Yates shuffle
+        for i in 0..nb_mines {
+            let j = i + rng.range((candidates.len() - i) as u32) as usize;
+            candidates.swap(i, j);
+            result[candidates[i] as usize] = true;
+        }
+        result
+    }
+}
+
+/// Writes the name of a preset, or `WIDTHxHEIGHTxMINES`, as parsed back by `from_str`.
+impl fmt::Display for BoardSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BoardSize::BEGINNER => write!(f, "beginner"),
+            BoardSize::INTERMEDIATE => write!(f, "intermediate"),
+            BoardSize::EXPERT => write!(f, "expert"),
+            _ => write!(f, "{}x{}x{}", self.width, self.height, self.mines),
+        }
+    }
+}
+
+/// Parses `beginner`, `intermediate`, `expert` or a custom `WIDTHxHEIGHTxMINES`.
+impl FromStr for BoardSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BoardSize, String> {
+        match s {
+            "beginner" => return Ok(BoardSize::BEGINNER),
+            "intermediate" => return Ok(BoardSize::INTERMEDIATE),
+            "expert" => return Ok(BoardSize::EXPERT),
+            _ => {},
+        }
+        let parts: Vec<u32> = s.split('x')
+            .map(|p| p.parse().map_err(|_| format!("invalid board '{}'", s)))
+            .collect::<Result<_, _>>()?;
+        match parts[..] {
+            [width, height, mines] => BoardSize::custom(width, height, mines),
+            _ => Err(format!("invalid board '{}', expected WIDTHxHEIGHTxMINES", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn parse_board() {
+        assert_eq!("expert".parse(), Ok(BoardSize::EXPERT));
+        assert_eq!("20x10x30".parse(), Ok(BoardSize { width: 20, height: 10, mines: 30 }));
+        assert!("3x3x9".parse::<BoardSize>().is_err());
+        assert!("20x10".parse::<BoardSize>().is_err());
+        assert!("huge".parse::<BoardSize>().is_err());
+        assert_eq!(BoardSize { width: 20, height: 10, mines: 30 }.to_string(), "20x10x30");
+        assert_eq!(BoardSize::BEGINNER.to_string(), "beginner");
+    }
+
+    #[test]
+    fn board_leaves_room_for_the_first_click() {
+        let config = |board: &str, topology, neighbourhood| Config {
+            board: Some(board.parse().unwrap()),
+            topology,
+            neighbourhood,
+            ..Config::default()
+        };
+        assert!(config("3x3x1", TopologyKind::Square, Neighbourhood::Classic).check().is_err());
+        assert!(config("10x10x91", TopologyKind::Square, Neighbourhood::Classic).check().is_ok());
+        // The 5x5 rule keeps 25 cells free, triangles 13
+        assert!(config("10x10x76", TopologyKind::Square, Neighbourhood::Extended).check().is_err());
+        assert!(config("10x10x75", TopologyKind::Square, Neighbourhood::Extended).check().is_ok());
+        assert!(config("10x10x88", TopologyKind::Triangle, Neighbourhood::Classic).check().is_err());
+        assert!(config("10x10x87", TopologyKind::Triangle, Neighbourhood::Classic).check().is_ok());
+        let unsafe_start = Config { safe_first_click: false, ..config("3x3x8", TopologyKind::Square, Neighbourhood::Classic) };
+        assert!(unsafe_start.check().is_ok());
+    }
+}
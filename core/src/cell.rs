@@ -0,0 +1,38 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CellContents {
+    /// Number of mines around the cell.
+    Empty(i32),
+    /// Mines counted in the numbers around: 1, 2 or 3, or -1 for a negative mine.
+    Mine(i32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub revealed: bool,
+    /// Mines the player marked the cell with, 0 when it isn't flagged.
+    pub flag: i32,
+    pub contents: CellContents,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self { revealed: false, flag: 0, contents: CellContents::Empty(0) }
+    }
+}
+
+impl Cell {
+    pub fn is_mine(&self) -> bool {
+        matches!(self.contents, CellContents::Mine(_))
+    }
+
+    pub fn is_flagged(&self) -> bool {
+        self.flag != 0
+    }
+}
+
+/// Offsets of the cells counted in a cell's number.
+pub const NEIGHBOURS: [(i32, i32); 8] = [
+    (-1, -1), (0, -1), (1, -1),
+    (-1, 0), (1, 0),
+    (-1, 1), (0, 1), (1, 1),
+];
@@ -0,0 +1,122 @@
+use crate::*;
+
+pub const CHUNK_SIZE: u32 = 16;
+pub const CHUNK_LEN: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+pub struct Chunk {
+    elems : [Cell; CHUNK_LEN],
+    position: ChunkPos,
+    /// Set once a cell was changed, so the chunk can't be regenerated from the seed anymore.
+    modified: bool,
+}
+
+impl Chunk {
+    /// Neighbour counts are left at 0, they depend on the surrounding chunks
+    /// and are filled in by `Grid::count_neighbours`.
+    /// Mines of `layout` get the weight given by `weight` for their position.
+    pub fn new(position: ChunkPos, layout: [bool; CHUNK_LEN], weight: impl Fn(WorldPos) -> i32) -> Chunk {
+        let mut result = Chunk {
+            elems: [Default::default(); CHUNK_LEN],
+            position,
+            modified: false,
+        };
+        result.set_mines(layout, weight);
+        result
+    }
+
+    /// Chunk restored from saved cells, considered modified.
+    pub fn from_cells(position: ChunkPos, elems: [Cell; CHUNK_LEN]) -> Chunk {
+        Chunk { elems, position, modified: true }
+    }
+
+    /// Mines of a chunk of the infinite world.
+    /// The mine layout is a pure function of the world seed and the chunk position,
+    /// so it is known before a chunk is generated and after it has been evicted.
+    /// No mine is placed on the cells of `safe`, the chunk keeps its `mines` though,
+    /// as long as the other cells can hold them all.
+    pub fn mine_layout(position: ChunkPos, mines: u32, seed: u64, safe: &[WorldPos]) -> [bool; CHUNK_LEN] {
+        let mut result = [false; CHUNK_LEN];
+        let mut rng = Rng::for_chunk(seed, position);
+        let is_safe = |local: LocalPos| safe.contains(&position.world(local));
+        let free = (0..CHUNK_LEN).filter(|i| !is_safe(LocalPos::from_idx(*i))).count() as u32;
+        for _ in 0..mines.min(free) {
+            loop {
+                let x = rng.range(CHUNK_SIZE);
+                let y = rng.range(CHUNK_SIZE);
+                let local = LocalPos::new(x, y);
+                if !result[local.idx()] && !is_safe(local) {
+                    result[local.idx()] = true;
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    /// Replaces the mines of the chunk, keeping what the player did.
+    /// Neighbour counts have to be computed again afterwards.
+    pub(crate) fn set_mines(&mut self, layout: [bool; CHUNK_LEN], weight: impl Fn(WorldPos) -> i32) {
+        for (i, (cell, mine)) in self.elems.iter_mut().zip(layout).enumerate() {
+            let pos = self.position.world(LocalPos::from_idx(i));
+            cell.contents = if mine { CellContents::Mine(weight(pos)) } else { CellContents::Empty(0) };
+        }
+    }
+
+    pub fn position(&self) -> ChunkPos {
+        self.position
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub(crate) fn mark_modified(&mut self) {
+        self.modified = true;
+    }
+
+    pub fn mines(&self) -> [bool; CHUNK_LEN] {
+        let mut result = [false; CHUNK_LEN];
+        for (mine, cell) in result.iter_mut().zip(self.elems.iter()) {
+            *mine = cell.is_mine();
+        }
+        result
+    }
+
+    /// Every cell of the chunk, row by row.
+    pub fn cells(&self) -> impl Iterator<Item = (LocalPos, &Cell)> {
+        self.elems.iter().enumerate().map(|(i, c)| (LocalPos::from_idx(i), c))
+    }
+
+    pub fn get_cell(&self, pos: LocalPos) -> &Cell {
+        &self.elems[pos.idx()]
+    }
+
+    pub fn get_cell_mut(&mut self, pos: LocalPos) -> &mut Cell {
+        self.modified = true;
+        &mut self.elems[pos.idx()]
+    }
+
+    /// Mutable access to every cell for bookkeeping that isn't a player action, e.g. neighbour counts.
+    /// Doesn't mark the chunk as modified.
+    pub(crate) fn cells_mut(&mut self) -> impl Iterator<Item = (LocalPos, &mut Cell)> {
+        self.elems.iter_mut().enumerate().map(|(i, c)| (LocalPos::from_idx(i), c))
+    }
+
+    pub fn flag_all_mines(&mut self) {
+        for c in &mut self.elems {
+            if let (CellContents::Mine(weight), false) = (c.contents, c.revealed) {
+                c.flag = weight;
+                self.modified = true;
+            }
+        }
+    }
+
+    pub fn show_all_mines(&mut self) {
+        for c in &mut self.elems {
+            if c.is_mine() {
+                c.revealed = true;
+                self.modified = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashSet;
+use std::fs;
+use std::time::Duration;
+
+use crate::*;
+use crate::codec::Reader;
+
+/// Largest `lock_radius`, the lock area growing with its square.
+pub const MAX_LOCK_RADIUS: u32 = 5;
+
+/// Options of a game, decided before it starts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Finite board to play on, `None` for the infinite world.
+    pub board: Option<BoardSize>,
+    /// The first reveal always opens a cell with no mine around it.
+    pub safe_first_click: bool,
+    /// Mines are placed so that the game can be won without guessing.
+    /// Needs `safe_first_click` on finite boards, to know where the player starts.
+    pub no_guess: bool,
+    /// Time spent at most looking for such a layout, per board or per chunk of the infinite world.
+    pub no_guess_budget: Duration,
+    /// A click that would lose the game is taken back instead, once lives to spare are spent.
+    pub undo_losing_click: bool,
+    /// Mines the player can set off before losing, 1 for the classic game.
+    pub lives: u32,
+    /// Cells at most this many neighbours away from a mine set off are locked: the player can no longer reveal, chord or flag them.
+    pub lock_radius: u32,
+    /// Mines per chunk of the infinite world.
+    pub density: DensityCurve,
+    /// Shape of the cells, and which of them touch.
+    pub topology: TopologyKind,
+    /// Cells counted in the numbers of square cells.
+    pub neighbourhood: Neighbourhood,
+    /// Layers of a voxel board, the rows of the board being split evenly between them.
+    pub layers: u32,
+    /// How many mines each mined cell holds.
+    pub weights: MineWeights,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            board: None,
+            safe_first_click: true,
+            no_guess: false,
+            no_guess_budget: Duration::from_millis(50),
+            undo_losing_click: false,
+            lives: 1,
+            lock_radius: 2,
+            density: DensityCurve::default(),
+            topology: TopologyKind::Square,
+            neighbourhood: Neighbourhood::Classic,
+            layers: 1,
+            weights: MineWeights::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Checks that a finite board has room for its mines once the area opened by the first click is left out.
+    /// That area depends on the shape of the cells and the neighbourhood, e.g. 25 cells with the 5x5 rule.
+    pub fn check(&self) -> Result<(), String> {
+        if self.lock_radius > MAX_LOCK_RADIUS {
+            return Err(format!("a lock radius of {} is above the largest, {}", self.lock_radius, MAX_LOCK_RADIUS));
+        }
+        let Some(size) = self.board else {
+            return match self.topology {
+                TopologyKind::Torus | TopologyKind::Voxel => Err(format!("a {} needs a board to wrap or stack", self.topology)),
+                _ => Ok(()),
+            };
+        };
+        if self.topology == TopologyKind::Voxel && (self.layers == 0 || !size.height.is_multiple_of(self.layers)) {
+            return Err(format!("{} layers don't split the {} rows of the board evenly", self.layers, size.height));
+        }
+        let safe = if self.safe_first_click { self.safe_area(size) } else { 0 };
+        let free = (size.width * size.height).saturating_sub(safe as u32);
+        if size.mines > free {
+            return Err(format!("a {}x{} board holds at most {} mines with {} cells left free by the first click", size.width, size.height, free, safe));
+        }
+        Ok(())
+    }
+
+    /// Cells of `size` opened by a first click in the middle of the board, where they are the most.
+    fn safe_area(&self, size: BoardSize) -> usize {
+        let topology = self.topology.build(Some(size), &self.neighbourhood, self.layers);
+        let middle = WorldPos::new(size.width as i32 / 2, size.height as i32 / 2);
+        // Cells of some shapes don't all have as many neighbours, e.g. triangles pointing up or down
+        NEIGHBOURS.iter().chain(&[(0, 0)])
+            .map(|o| topology.area(middle + o).into_iter().filter(|p| size.contains(*p)).collect::<HashSet<_>>().len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Applies an argument of the command line, taking its value from `args` if it has one:
+    /// `no-guess`, `casual`, `density <curve>`, `rule <neighbourhood or file>`, `weights <weights>`,
+    /// `layers <n>`, `lives <n>`, `lock <radius>`, a topology or a board size. Errors start with what was ignored.
+    pub fn parse_arg(&mut self, arg: &str, args: &mut impl Iterator<Item = String>) -> Result<(), String> {
+        match arg {
+            "no-guess" => self.no_guess = true,
+            "casual" => self.undo_losing_click = true,
+            "density" => match args.next().map(|d| d.parse()) {
+                Some(Ok(d)) => self.density = d,
+                Some(Err(e)) => return Err(format!("density: {}", e)),
+                None => return Err("density: expected a density curve".to_string()),
+            },
+            "rule" => match args.next().map(|r| fs::read_to_string(&r).unwrap_or(r).parse()) {
+                Some(Ok(n)) => self.neighbourhood = n,
+                Some(Err(e)) => return Err(format!("rule: {}", e)),
+                None => return Err("rule: expected a neighbourhood".to_string()),
+            },
+            "weights" => match args.next().map(|w| w.parse()) {
+                Some(Ok(w)) => self.weights = w,
+                Some(Err(e)) => return Err(format!("weights: {}", e)),
+                None => return Err("weights: expected mine weights".to_string()),
+            },
+            "layers" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => self.layers = n,
+                _ => return Err("layers: expected a number of layers above 0".to_string()),
+            },
+            "lives" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => self.lives = n,
+                _ => return Err("lives: expected a number of lives above 0".to_string()),
+            },
+            "lock" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n <= MAX_LOCK_RADIUS => self.lock_radius = n,
+                _ => return Err(format!("lock: expected a lock radius up to {}", MAX_LOCK_RADIUS)),
+            },
+            _ => match (arg.parse::<TopologyKind>(), arg.parse::<BoardSize>()) {
+                (Ok(t), _) => self.topology = t,
+                (_, Ok(b)) => self.board = Some(b),
+                (_, Err(e)) => return Err(format!("argument: {}", e)),
+            },
+        }
+        Ok(())
+    }
+
+    /// Name of the kind of game played, statistics being kept per mode.
+    /// Games that need no guess or take back losing clicks are kept apart.
+    pub fn mode(&self) -> String {
+        let mut result = self.board.map_or("infinite".to_string(), |b| b.to_string());
+        if self.no_guess {
+            result += " no-guess";
+        }
+        if self.undo_losing_click {
+            result += " casual";
+        }
+        if self.lives > 1 {
+            result += &format!(" {}-lives", self.lives);
+        }
+        if self.board.is_none() && self.density != DensityCurve::default() {
+            result += &format!(" density-{}", self.density);
+        }
+        match self.topology {
+            TopologyKind::Square => {},
+            TopologyKind::Voxel => result += &format!(" {}-{}", self.topology, self.layers),
+            _ => result += &format!(" {}", self.topology),
+        }
+        if self.topology.is_square() && self.neighbourhood != Neighbourhood::Classic {
+            result += &format!(" {}", self.neighbourhood);
+        }
+        if !self.weights.is_classic() {
+            result += &format!(" weights-{}", self.weights);
+        }
+        result
+    }
+
+    /// Layout, shared by the files that store a config:
+    ///
+    /// ```text
+    /// board present: u8 | width: u32 | height: u32 | mines: u32
+    /// safe first click: u8 | no guess: u8 | no guess budget in ms: u32
+    /// undo losing click: u8 | lives: u32 | lock radius: u32
+    /// density at the origin: f32 | density far away: f32 | density ramp: u32 | density variance: f32
+    /// topology: u8 | neighbourhood: u8 | custom offset count: u8 | x: i8 | y: i8 per custom offset
+    /// double mines: f32 | triple mines: f32 | negative mines: f32 | layers: u32
+    /// ```
+    ///
+    /// Topologies are 0 square, 1 hex, 2 triangle, 3 torus and 4 voxel. Neighbourhoods are 0 classic, 1 cross,
+    /// 2 knight, 3 extended and 4 custom, only custom ones having a count and offsets.
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        let board = self.board.unwrap_or(BoardSize { width: 0, height: 0, mines: 0 });
+        out.push(self.board.is_some() as u8);
+        out.extend_from_slice(&board.width.to_le_bytes());
+        out.extend_from_slice(&board.height.to_le_bytes());
+        out.extend_from_slice(&board.mines.to_le_bytes());
+        out.push(self.safe_first_click as u8);
+        out.push(self.no_guess as u8);
+        out.extend_from_slice(&(self.no_guess_budget.as_millis() as u32).to_le_bytes());
+        out.push(self.undo_losing_click as u8);
+        out.extend_from_slice(&self.lives.to_le_bytes());
+        out.extend_from_slice(&self.lock_radius.to_le_bytes());
+        let d = &self.density;
+        out.extend_from_slice(&d.start.to_le_bytes());
+        out.extend_from_slice(&d.end.to_le_bytes());
+        out.extend_from_slice(&d.ramp.to_le_bytes());
+        out.extend_from_slice(&d.variance.to_le_bytes());
+        out.push(TopologyKind::ALL.iter().position(|t| *t == self.topology).unwrap() as u8);
+        out.push(match self.neighbourhood {
+            Neighbourhood::Classic => 0,
+            Neighbourhood::Cross => 1,
+            Neighbourhood::Knight => 2,
+            Neighbourhood::Extended => 3,
+            Neighbourhood::Custom(_) => 4,
+        });
+        if let Neighbourhood::Custom(offsets) = &self.neighbourhood {
+            out.push(offsets.len() as u8);
+            for (x, y) in offsets {
+                out.extend_from_slice(&[*x as i8 as u8, *y as i8 as u8]);
+            }
+        }
+        let w = &self.weights;
+        out.extend_from_slice(&w.double.to_le_bytes());
+        out.extend_from_slice(&w.triple.to_le_bytes());
+        out.extend_from_slice(&w.negative.to_le_bytes());
+        out.extend_from_slice(&self.layers.to_le_bytes());
+    }
+
+    pub(crate) fn decode(reader: &mut Reader) -> Result<Config, String> {
+        let has_board = reader.bool()?;
+        let (width, height, mines) = (reader.u32()?, reader.u32()?, reader.u32()?);
+        let mut result = Config {
+            board: if has_board { Some(BoardSize::custom(width, height, mines)?) } else { None },
+            safe_first_click: reader.bool()?,
+            no_guess: reader.bool()?,
+            no_guess_budget: Duration::from_millis(reader.u32()? as u64),
+            undo_losing_click: reader.bool()?,
+            lives: reader.u32()?,
+            lock_radius: reader.u32()?,
+            ..Config::default()
+        };
+        if result.lives == 0 {
+            return Err("a game needs at least one life".to_string());
+        }
+        result.density = DensityCurve {
+            start: reader.f32()?,
+            end: reader.f32()?,
+            ramp: reader.u32()?,
+            variance: reader.f32()?,
+        };
+        result.density.check()?;
+        let topology = reader.u8()?;
+        result.topology = *TopologyKind::ALL.get(topology as usize).ok_or(format!("unknown topology {}", topology))?;
+        result.neighbourhood = match reader.u8()? {
+            0 => Neighbourhood::Classic,
+            1 => Neighbourhood::Cross,
+            2 => Neighbourhood::Knight,
+            3 => Neighbourhood::Extended,
+            4 => {
+                let mut offsets = Vec::new();
+                for _ in 0..reader.u8()? {
+                    offsets.push((reader.u8()? as i8 as i32, reader.u8()? as i8 as i32));
+                }
+                Neighbourhood::custom(offsets)?
+            },
+            n => return Err(format!("unknown neighbourhood {}", n)),
+        };
+        result.weights = MineWeights { double: reader.f32()?, triple: reader.f32()?, negative: reader.f32()? };
+        result.weights.check()?;
+        result.layers = reader.u32()?;
+        result.check()?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn arguments_set_up_the_game() {
+        let mut config = Config::default();
+        let mut args = ["lives", "3", "lock", "1", "hex", "expert", "density", "0.2", "rule"].map(String::from).into_iter();
+        while let Some(arg) = args.next() {
+            let result = config.parse_arg(&arg, &mut args);
+            assert_eq!(result.is_err(), arg == "rule", "{}", arg);
+        }
+        assert_eq!(config.lives, 3);
+        assert_eq!(config.lock_radius, 1);
+        assert_eq!(config.topology, TopologyKind::Hex);
+        assert_eq!(config.board, Some(BoardSize::EXPERT));
+        assert_eq!(config.density, DensityCurve::flat(0.2));
+
+        let mut args = std::iter::empty();
+        assert_eq!(config.parse_arg("lives", &mut args), Err("lives: expected a number of lives above 0".to_string()));
+        let mut args = ["6"].map(String::from).into_iter();
+        assert_eq!(config.parse_arg("lock", &mut args), Err("lock: expected a lock radius up to 5".to_string()));
+        assert_eq!(config.parse_arg("3x3x9", &mut args), Err("argument: a 3x3 board holds at most 8 mines".to_string()));
+    }
+
+    #[test]
+    fn topology_fits_the_board() {
+        let voxel = |board: Option<&str>, layers| Config {
+            board: board.map(|b| b.parse().unwrap()),
+            topology: TopologyKind::Voxel,
+            layers,
+            ..Config::default()
+        };
+        assert!(voxel(Some("8x24x40"), 3).check().is_ok());
+        assert_eq!(voxel(Some("8x25x40"), 3).check(), Err("3 layers don't split the 25 rows of the board evenly".to_string()));
+        assert!(voxel(Some("8x24x40"), 0).check().is_err());
+        assert_eq!(voxel(None, 3).check(), Err("a voxel needs a board to wrap or stack".to_string()));
+        let torus = Config { topology: TopologyKind::Torus, ..Config::default() };
+        assert!(torus.check().is_err());
+        assert!(Config { board: Some(BoardSize::BEGINNER), ..torus }.check().is_ok());
+    }
+
+    #[test]
+    fn lock_radius_is_bounded() {
+        let config = Config { lock_radius: u32::MAX, ..Config::default() };
+        let mut data = Vec::new();
+        config.encode(&mut data);
+        let error = Config::decode(&mut codec::Reader::new(&data));
+        assert_eq!(error, Err(format!("a lock radius of {} is above the largest, 5", u32::MAX)));
+    }
+}
@@ -0,0 +1,145 @@
+//! Cell coordinates at the three scales of the infinite grid.
+//!
+//! Conversions use Euclidean division, so negative coordinates round towards negative infinity:
+//! world cell -1 is the last cell of chunk -1, not a cell of chunk 0.
+
+use std::ops::Add;
+
+use crate::CHUNK_SIZE;
+
+/// Position of a cell in the whole world.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct WorldPos {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Position of a chunk, in chunks.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Position of a cell inside its chunk, both coordinates in `0..CHUNK_SIZE`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct LocalPos {
+    pub x: u32,
+    pub y: u32,
+}
+
+impl WorldPos {
+    pub const fn new(x: i32, y: i32) -> WorldPos {
+        WorldPos { x, y }
+    }
+
+    pub fn chunk(self) -> ChunkPos {
+        ChunkPos::new(self.x.div_euclid(CHUNK_SIZE as i32), self.y.div_euclid(CHUNK_SIZE as i32))
+    }
+
+    pub fn local(self) -> LocalPos {
+        LocalPos::new(self.x.rem_euclid(CHUNK_SIZE as i32) as u32, self.y.rem_euclid(CHUNK_SIZE as i32) as u32)
+    }
+
+    /// Cell containing the point (x, y), given in cells.
+    pub fn containing(x: f32, y: f32) -> WorldPos {
+        WorldPos::new(x.floor() as i32, y.floor() as i32)
+    }
+}
+
+impl ChunkPos {
+    pub const fn new(x: i32, y: i32) -> ChunkPos {
+        ChunkPos { x, y }
+    }
+
+    /// World position of the chunk's first cell.
+    pub fn origin(self) -> WorldPos {
+        WorldPos::new(self.x * CHUNK_SIZE as i32, self.y * CHUNK_SIZE as i32)
+    }
+
+    pub fn world(self, local: LocalPos) -> WorldPos {
+        let origin = self.origin();
+        WorldPos::new(origin.x + local.x as i32, origin.y + local.y as i32)
+    }
+}
+
+impl LocalPos {
+    pub const fn new(x: u32, y: u32) -> LocalPos {
+        debug_assert!(x < CHUNK_SIZE && y < CHUNK_SIZE);
+        LocalPos { x, y }
+    }
+
+    /// Index of the cell in its chunk's storage.
+    pub fn idx(self) -> usize {
+        (self.x + self.y * CHUNK_SIZE) as usize
+    }
+
+    pub fn from_idx(idx: usize) -> LocalPos {
+        LocalPos::new(idx as u32 % CHUNK_SIZE, idx as u32 / CHUNK_SIZE)
+    }
+}
+
+/// Rectangle of chunks, bounds included.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ChunkRect {
+    pub min: ChunkPos,
+    pub max: ChunkPos,
+}
+
+impl ChunkRect {
+    pub fn new(min: ChunkPos, max: ChunkPos) -> ChunkRect {
+        ChunkRect { min, max }
+    }
+
+    pub fn contains(&self, pos: ChunkPos) -> bool {
+        pos.x >= self.min.x && pos.x <= self.max.x && pos.y >= self.min.y && pos.y <= self.max.y
+    }
+
+    /// Every chunk of the rectangle, row by row.
+    pub fn iter(self) -> impl Iterator<Item = ChunkPos> {
+        (self.min.y..=self.max.y).flat_map(move |y| (self.min.x..=self.max.x).map(move |x| ChunkPos::new(x, y)))
+    }
+}
+
+impl Add<(i32, i32)> for WorldPos {
+    type Output = WorldPos;
+
+    fn add(self, offset: (i32, i32)) -> WorldPos {
+        WorldPos::new(self.x + offset.0, self.y + offset.1)
+    }
+}
+
+impl Add<&(i32, i32)> for WorldPos {
+    type Output = WorldPos;
+
+    fn add(self, offset: &(i32, i32)) -> WorldPos {
+        self + *offset
+    }
+}
+
+impl Add<(i32, i32)> for ChunkPos {
+    type Output = ChunkPos;
+
+    fn add(self, offset: (i32, i32)) -> ChunkPos {
+        ChunkPos::new(self.x + offset.0, self.y + offset.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coords::*;
+
+    #[test]
+    fn negative_coordinates() {
+        let size = CHUNK_SIZE as i32;
+        assert_eq!(WorldPos::new(-1, 0).chunk(), ChunkPos::new(-1, 0));
+        assert_eq!(WorldPos::new(-1, 0).local(), LocalPos::new(CHUNK_SIZE - 1, 0));
+        assert_eq!(WorldPos::new(-size, size).chunk(), ChunkPos::new(-1, 1));
+        assert_eq!(WorldPos::new(-size - 1, 0).chunk(), ChunkPos::new(-2, 0));
+        for x in -40..40 {
+            let pos = WorldPos::new(x, -x);
+            assert_eq!(pos.chunk().world(pos.local()), pos);
+        }
+        assert_eq!(WorldPos::containing(-0.5, 0.5), WorldPos::new(-1, 0));
+    }
+}
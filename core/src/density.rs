@@ -0,0 +1,148 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::*;
+
+/// Mine density of the infinite world, going from `start` around the origin to `end` further away.
+/// Each chunk gets an exact number of mines from it, so difficulty doesn't depend on luck.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DensityCurve {
+    /// Density at the origin.
+    pub start: f32,
+    /// Density from `ramp` cells away from the origin on.
+    pub end: f32,
+    /// Distance in cells over which the density goes linearly from `start` to `end`.
+    pub ramp: u32,
+    /// Largest random change of the density of a chunk, either way.
+    pub variance: f32,
+}
+
+impl Default for DensityCurve {
+    fn default() -> Self {
+        DensityCurve::flat(0.1)
+    }
+}
+
+impl DensityCurve {
+    /// Highest density. A chunk with a large safe zone may get fewer mines, see `Chunk::mine_layout`.
+    pub const MAX: f32 = 0.9;
+
+    pub fn flat(density: f32) -> DensityCurve {
+        DensityCurve { start: density, end: density, ramp: 0, variance: 0.0 }
+    }
+
+    /// Checks that densities are in range. Sparse worlds are fine: below about 0.1, openings may have
+    /// no end, and their flood fills stop at the loaded chunks until the player goes further.
+    pub fn check(&self) -> Result<(), String> {
+        for d in [self.start, self.end] {
+            if !(0.0..=Self::MAX).contains(&d) {
+                return Err(format!("densities must be between 0 and {}", Self::MAX));
+            }
+        }
+        if !(0.0..=Self::MAX).contains(&self.variance) {
+            return Err(format!("density variance must be between 0 and {}", Self::MAX));
+        }
+        Ok(())
+    }
+
+    /// Density of a chunk before variance, from the distance of its center to the origin.
+    pub fn base(&self, position: ChunkPos) -> f32 {
+        let center = position.origin() + (CHUNK_SIZE as i32 / 2, CHUNK_SIZE as i32 / 2);
+        let distance = ((center.x as f32).powi(2) + (center.y as f32).powi(2)).sqrt();
+        let t = if self.ramp == 0 { 1.0 } else { (distance / self.ramp as f32).min(1.0) };
+        self.start + (self.end - self.start) * t
+    }
+
+    /// Density of a chunk, variance included.
+    pub fn density(&self, position: ChunkPos, seed: u64) -> f32 {
+        let mut result = self.base(position);
+        if self.variance > 0.0 {
+            // Drawn apart from the layout, so that variance doesn't change where mines go
+            let mut rng = Rng::for_chunk(seed ^ 0x00DE_4517_7CAB_1E5A, position);
+            let unit = (rng.next_u64() >> 40) as f32 / (1 << 24) as f32;
+            result += self.variance * (2.0 * unit - 1.0);
+        }
+        result.clamp(0.0, Self::MAX)
+    }
+
+    /// Number of mines of a chunk.
+    pub fn mines(&self, position: ChunkPos, seed: u64) -> u32 {
+        (self.density(position, seed) * CHUNK_LEN as f32) as u32
+    }
+}
+
+/// Writes `START`, or `START-END/RAMP`, followed by `~VARIANCE` if there is any, as parsed by `from_str`.
+impl fmt::Display for DensityCurve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)?;
+        } else {
+            write!(f, "{}-{}/{}", self.start, self.end, self.ramp)?;
+        }
+        if self.variance > 0.0 {
+            write!(f, "~{}", self.variance)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `START`, for the same density everywhere, or `START-END/RAMP`,
+/// optionally followed by `~VARIANCE`, e.g. `0.05-0.2/2000~0.02`.
+impl FromStr for DensityCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DensityCurve, String> {
+        let invalid = || format!("invalid density '{}', expected START-END/RAMP~VARIANCE", s);
+        let (curve, variance) = s.split_once('~').unwrap_or((s, "0"));
+        let (densities, ramp) = curve.split_once('/').unwrap_or((curve, "0"));
+        let (start, end) = densities.split_once('-').unwrap_or((densities, densities));
+        let result = DensityCurve {
+            start: start.parse().map_err(|_| invalid())?,
+            end: end.parse().map_err(|_| invalid())?,
+            ramp: ramp.parse().map_err(|_| invalid())?,
+            variance: variance.parse().map_err(|_| invalid())?,
+        };
+        result.check()?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn harder_further_away() {
+        let curve: DensityCurve = "0.05-0.25/1000~0.02".parse().unwrap();
+        assert_eq!(curve.to_string().parse(), Ok(curve));
+        let near = curve.mines(ChunkPos::new(0, 0), 1);
+        let far = curve.mines(ChunkPos::new(200, -3), 1);
+        assert!((8..=18).contains(&near), "{} mines near the origin", near);
+        assert!((58..=69).contains(&far), "{} mines far away", far);
+        // The count is exact, and variance the same each time
+        let layout = Chunk::mine_layout(ChunkPos::new(200, -3), far, 1, &[]);
+        assert_eq!(layout.iter().filter(|m| **m).count() as u32, far);
+        assert_eq!(curve.mines(ChunkPos::new(200, -3), 1), far);
+
+        assert_eq!(DensityCurve::default().mines(ChunkPos::new(7, 7), 1), 25);
+        assert!("0.5-2/10".parse::<DensityCurve>().is_err());
+        assert!("dense".parse::<DensityCurve>().is_err());
+    }
+
+    #[test]
+    fn sparse_openings_stop_at_loaded_chunks() {
+        let mut grid = Grid::new(1);
+        grid.set_density("0.05-0.2/2000~0.02".parse().unwrap());
+        let view = ChunkRect::new(ChunkPos::new(-3, -3), ChunkPos::new(3, 3));
+        grid.update_chunks(view).unwrap();
+        let start = (0..CHUNK_LEN as i32).map(|i| WorldPos::new(i % 16, i / 16))
+            .find(|p| grid.get_cell(*p).unwrap().contents == CellContents::Empty(0))
+            .unwrap();
+        grid.reveal(start);
+        while grid.is_revealing() {
+            grid.continue_reveal(REVEAL_BUDGET);
+        }
+        assert!(grid.revealed_count() > 1);
+        assert_eq!(grid.loaded_chunks().count(), 49);
+    }
+}
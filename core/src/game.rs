@@ -0,0 +1,603 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    Playing,
+    Lost,
+    /// Every safe cell of a finite board is revealed.
+    Won,
+}
+
+/// Actions played at once and what they changed, to undo them.
+struct Step {
+    actions: Vec<Action>,
+    journal: Journal,
+    /// State and lives before the actions.
+    state: State,
+    lives: u32,
+}
+
+/// A game in progress: the board and whether it is still being played.
+pub struct Game {
+    pub grid: Grid,
+    pub config: Config,
+    state: State,
+    history: Vec<Step>,
+    /// Steps undone, the last one first to redo.
+    undone: Vec<Step>,
+    /// Step whose changes are still being journaled, as its flood fill may carry on over several updates.
+    current: Option<Step>,
+    /// Reveals, chords and flags asked by the player.
+    clicks: u32,
+    /// Mines that can still be set off before losing.
+    lives: u32,
+}
+
+impl Game {
+    pub fn new(mut grid: Grid, config: Config) -> Game {
+        grid.set_no_guess(config.no_guess.then_some(config.no_guess_budget));
+        grid.set_density(config.density);
+        grid.set_weights(config.weights);
+        grid.set_topology(config.topology.build(grid.board(), &config.neighbourhood, config.layers));
+        Game {
+            grid,
+            state: State::Playing,
+            history: Vec::new(),
+            undone: Vec::new(),
+            current: None,
+            clicks: 0,
+            lives: config.lives.max(1),
+            config,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn clicks(&self) -> u32 {
+        self.clicks
+    }
+
+    pub fn lives(&self) -> u32 {
+        self.lives
+    }
+
+    /// Cells at most `lock_radius` neighbours away from `pos`, which a mine set off there locks.
+    pub fn lock_area(&self, pos: WorldPos) -> HashSet<WorldPos> {
+        let topology = self.grid.topology();
+        let mut result = HashSet::from([pos]);
+        let mut border = vec![pos];
+        for _ in 0..self.config.lock_radius {
+            border = border.iter().flat_map(|p| topology.neighbours(*p)).filter(|p| result.insert(*p)).collect();
+        }
+        result
+    }
+
+    /// Whether `pos` is close enough to a mine set off to be out of the player's reach.
+    /// Neighbours go both ways, so the mines locking `pos` are in its own lock area.
+    pub fn is_locked(&self, pos: WorldPos) -> bool {
+        self.lock_area(pos).into_iter().any(|p| self.grid.get_cell(p).is_some_and(|c| c.revealed && c.is_mine()))
+    }
+
+    /// A game carrying on from a save, with an empty history.
+    pub(crate) fn restored(grid: Grid, config: Config, state: State, clicks: u32, lives: u32) -> Game {
+        Game { state, clicks, lives, ..Game::new(grid, config) }
+    }
+
+    /// Takes the state and lives of a game played elsewhere, e.g. on a server.
+    pub(crate) fn set_state(&mut self, state: State, lives: u32) {
+        self.state = state;
+        self.lives = lives;
+    }
+
+    pub fn reveal(&mut self, pos: WorldPos) -> Revealed {
+        self.clicks += 1;
+        self.play(vec![Action::Reveal(pos)])
+    }
+
+    pub fn chord(&mut self, pos: WorldPos) -> Revealed {
+        self.clicks += 1;
+        self.play(vec![Action::Chord(pos)])
+    }
+
+    pub fn flag(&mut self, pos: WorldPos) {
+        self.clicks += 1;
+        self.play(vec![Action::Flag(pos)]);
+    }
+
+    pub fn show_all_mines(&mut self) {
+        self.play(vec![Action::ShowMines]);
+    }
+
+    /// Plays `actions` as a single step of the history, forgetting what was undone.
+    fn play(&mut self, actions: Vec<Action>) -> Revealed {
+        self.undone.clear();
+        let result = self.run_step(actions);
+        // With lives to spare, a mine only takes one of them
+        if result.mines > 0 && self.config.undo_losing_click && self.state == State::Lost {
+            self.undo();
+            self.undone.clear();
+        }
+        result
+    }
+
+    /// Actions played while a flood fill carries on join its step, which can't be undone in part.
+    fn run_step(&mut self, actions: Vec<Action>) -> Revealed {
+        if self.current.is_none() || !self.grid.is_revealing() {
+            self.close_step();
+            self.grid.start_journal();
+            self.current = Some(Step { actions: Vec::new(), journal: Journal::default(), state: self.state, lives: self.lives });
+        }
+        let mut result = Revealed::default();
+        for a in &actions {
+            result.append(self.run(*a));
+        }
+        self.current.as_mut().unwrap().actions.extend(actions);
+        result
+    }
+
+    fn run(&mut self, action: Action) -> Revealed {
+        if self.state != State::Playing && action != Action::ShowMines {
+            return Revealed::default();
+        }
+        if let Action::Reveal(pos) | Action::Chord(pos) | Action::Flag(pos) = action {
+            if self.is_locked(pos) {
+                return Revealed::default();
+            }
+        }
+        let result = match action {
+            Action::Reveal(pos) => {
+                if self.config.safe_first_click && self.grid.safe_zone().is_none() {
+                    self.grid.set_safe_zone(pos);
+                }
+                self.grid.reveal(pos)
+            },
+            Action::Chord(pos) => self.grid.chord(pos),
+            Action::Flag(pos) => {
+                self.grid.flag(pos);
+                Revealed::default()
+            },
+            Action::ShowMines => {
+                self.grid.show_all_mines();
+                Revealed::default()
+            },
+            Action::Camera { .. } | Action::Undo | Action::Redo | Action::AutoFlag => Revealed::default(),
+        };
+        self.check_end(&result);
+        result
+    }
+
+    /// Ends the step in progress, and keeps it in the history if it changed anything.
+    fn close_step(&mut self) {
+        let Some(mut step) = self.current.take() else {
+            return;
+        };
+        step.journal = self.grid.take_journal().unwrap_or_default();
+        if !step.journal.is_empty() {
+            self.history.push(step);
+        }
+    }
+
+    /// Takes back the last step, which may have lost the game.
+    pub fn undo(&mut self) -> bool {
+        self.close_step();
+        let Some(step) = self.history.pop() else {
+            return false;
+        };
+        self.grid.undo(&step.journal);
+        self.state = step.state;
+        self.lives = step.lives;
+        self.undone.push(step);
+        true
+    }
+
+    /// Plays again the last step undone.
+    pub fn redo(&mut self) -> bool {
+        let Some(step) = self.undone.pop() else {
+            return false;
+        };
+        self.run_step(step.actions);
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty() || self.current.is_some()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// A hidden cell that is certainly safe, if the solver finds one.
+    pub fn hint(&self) -> Option<WorldPos> {
+        Solver::new(&self.grid).solve().safe.first().copied()
+    }
+
+    /// Chance of being a mine of the hidden cells next to a number.
+    pub fn probabilities(&self) -> HashMap<WorldPos, f32> {
+        let density = match self.grid.board() {
+            Some(size) => {
+                // Mines left over the cells left
+                let (mut hidden, mut flags) = (0, 0);
+                for chunk in self.grid.loaded_chunks() {
+                    for (local, cell) in chunk.cells() {
+                        if !self.grid.contains(chunk.position().world(local)) {
+                            continue;
+                        }
+                        // Mines set off are as good as flagged
+                        if cell.is_flagged() || cell.revealed && cell.is_mine() {
+                            flags += 1;
+                        } else if !cell.revealed {
+                            hidden += 1;
+                        }
+                    }
+                }
+                (size.mines.saturating_sub(flags) as f32 / hidden.max(1) as f32).min(0.99)
+            },
+            None => {
+                // Average over the chunks around, variance aside as it is unknown to the player
+                let (mut sum, mut nb) = (0.0, 0);
+                for chunk in self.grid.loaded_chunks() {
+                    sum += self.config.density.base(chunk.position());
+                    nb += 1;
+                }
+                sum / nb.max(1) as f32
+            },
+        };
+        Solver::new(&self.grid).probabilities(density)
+    }
+
+    /// Flags every cell the solver knows is a mine, returning the cells flagged.
+    /// Weighted mines are flagged as many times as it takes to mark their weight.
+    pub fn auto_flag(&mut self) -> Vec<WorldPos> {
+        if self.state != State::Playing {
+            return Vec::new();
+        }
+        let deductions = Solver::new(&self.grid).solve();
+        let mut result = deductions.mines;
+        result.retain(|pos| self.grid.get_cell(*pos).is_some_and(|c| !c.is_flagged()));
+        if !result.is_empty() {
+            let weights = self.config.weights;
+            let actions = result.iter()
+                .flat_map(|pos| std::iter::repeat_n(Action::Flag(*pos), weights.toggles(deductions.weights[pos])))
+                .collect();
+            self.play(actions);
+        }
+        result
+    }
+
+    /// Carries on with work spread over several frames, like big flood fills, returning the cells opened.
+    pub fn update(&mut self) -> Revealed {
+        if self.state == State::Playing && self.grid.is_revealing() {
+            let result = self.grid.continue_reveal(REVEAL_BUDGET);
+            self.check_end(&result);
+            return result;
+        }
+        Revealed::default()
+    }
+
+    fn check_end(&mut self, revealed: &Revealed) {
+        if revealed.mines > 0 {
+            self.lives = self.lives.saturating_sub(revealed.mines);
+            if self.lives == 0 {
+                self.loose();
+                return;
+            }
+        }
+        if self.grid.is_cleared() {
+            self.win();
+        }
+    }
+
+    fn win(&mut self) {
+        self.state = State::Won;
+        self.grid.flag_all_mines();
+    }
+
+    fn loose(&mut self) {
+        self.state = State::Lost;
+        self.grid.show_all_mines();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn first_click_is_safe() {
+        let seed = 5;
+        let mines = DensityCurve::default().mines(ChunkPos::new(0, 0), seed);
+        let layout = Chunk::mine_layout(ChunkPos::new(0, 0), mines, seed, &[]);
+        // Click right on a mine at a chunk corner, so the safe zone spans several chunks
+        let idx = layout.iter().position(|m| *m).unwrap();
+        let local = LocalPos::from_idx(idx);
+        let mut game = Game::new(Grid::new(seed), Config::default());
+        game.grid.update_chunks(ChunkRect::new(ChunkPos::new(-1, -1), ChunkPos::new(1, 1))).unwrap();
+        let pos = WorldPos::new(local.x as i32, local.y as i32);
+
+        let revealed = game.reveal(pos);
+        assert_eq!(revealed.mines, 0);
+        assert_eq!(game.state(), State::Playing);
+        assert_eq!(game.grid.get_cell(pos).unwrap().contents, CellContents::Empty(0));
+
+        let corner = WorldPos::new(0, 0);
+        let mut game = Game::new(Grid::new(seed), Config::default());
+        game.reveal(corner);
+        for o in NEIGHBOURS {
+            assert!(!game.grid.get_cell(corner + o).unwrap().is_mine());
+        }
+        assert_eq!(game.grid.get_cell(corner).unwrap().contents, CellContents::Empty(0));
+    }
+
+    #[test]
+    fn safe_zone_fits_at_max_density() {
+        // 49 safe cells in the middle of a chunk, where 230 mines would leave only 207 free
+        let square = (-3..=3).flat_map(|y| (-3..=3).map(move |x| (x, y))).filter(|o| *o != (0, 0)).collect();
+        let config = Config {
+            density: DensityCurve::flat(DensityCurve::MAX),
+            neighbourhood: Neighbourhood::custom(square).unwrap(),
+            ..Config::default()
+        };
+        let mut game = Game::new(Grid::new(5), config);
+        let pos = WorldPos::new(8, 8);
+        assert_eq!(game.reveal(pos).mines, 0);
+        for p in game.grid.topology().area(pos) {
+            assert!(!game.grid.get_cell(p).unwrap().is_mine());
+        }
+        let mines = game.grid.chunk_or_create(ChunkPos::new(0, 0)).mines().iter().filter(|m| **m).count();
+        assert_eq!(mines, CHUNK_LEN - 49);
+    }
+
+    #[test]
+    fn every_topology_plays() {
+        let size = BoardSize { width: 20, height: 12, mines: 30 };
+        let diagonals: Neighbourhood = "x.x/.o./x.x".parse().unwrap();
+        let rules = [Neighbourhood::Cross, Neighbourhood::Knight, Neighbourhood::Extended, diagonals];
+        let games = TopologyKind::ALL.map(|t| (t, Neighbourhood::Classic)).into_iter()
+            .chain(rules.map(|n| (TopologyKind::Square, n)));
+        for (topology, neighbourhood) in games {
+            let config = Config { board: Some(size), topology, neighbourhood, layers: 3, ..Config::default() };
+            let mut game = Game::new(Grid::fixed(8, size), config);
+            let start = WorldPos::new(0, 5);
+            game.reveal(start);
+            let mode = game.config.mode();
+            assert_eq!(game.grid.get_cell(start).unwrap().contents, CellContents::Empty(0), "{}", mode);
+            assert!(game.grid.revealed_count() > 1);
+            let neighbours = game.grid.topology().neighbours(start);
+            assert!(neighbours.iter().all(|p| game.grid.get_cell(*p).is_none_or(|c| c.revealed)));
+            // The torus wraps around to the last column
+            assert_eq!(neighbours.iter().any(|p| p.x == 19), game.config.topology == TopologyKind::Torus);
+            // Voxels touch the layers above and below, rows 8 to 11 being the last layer
+            assert_eq!(neighbours.iter().any(|p| p.y >= 8), game.config.topology == TopologyKind::Voxel);
+
+            for i in 0..size.width as i32 * size.height as i32 {
+                let pos = WorldPos::new(i % 20, i / 20);
+                let mines = game.grid.topology().neighbours(pos).iter()
+                    .filter(|p| game.grid.get_cell(**p).is_some_and(|c| c.is_mine()))
+                    .count() as i32;
+                if let CellContents::Empty(nb) = game.grid.get_cell(pos).unwrap().contents {
+                    assert_eq!(nb, mines, "{} cell {:?}", mode, pos);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn weighted_mines_agree_across_chunks() {
+        let config = Config { weights: "0.3/0.2/0.2".parse().unwrap(), ..Config::default() };
+        let seed = 6;
+        let number = |grid: &Grid, pos: WorldPos| match grid.get_cell(pos).unwrap().contents {
+            CellContents::Empty(nb) => Some(nb),
+            CellContents::Mine(_) => None,
+        };
+        let mut game = Game::new(Grid::new(seed), config.clone());
+        game.grid.update_chunks(ChunkRect::new(ChunkPos::new(-1, -1), ChunkPos::new(1, 1))).unwrap();
+        // The chunk on the right generated on its own, before those around it
+        let mut alone = Game::new(Grid::new(seed), config.clone());
+        alone.grid.update_chunks(ChunkRect::new(ChunkPos::new(1, 0), ChunkPos::new(1, 0))).unwrap();
+        let mut weights = Vec::new();
+        for y in 0..CHUNK_SIZE as i32 {
+            for x in 0..CHUNK_SIZE as i32 {
+                let pos = WorldPos::new(x, y);
+                let around: i32 = NEIGHBOURS.iter()
+                    .map(|o| match game.grid.get_cell(pos + *o).unwrap().contents {
+                        CellContents::Mine(weight) => weight,
+                        CellContents::Empty(_) => 0,
+                    })
+                    .sum();
+                match game.grid.get_cell(pos).unwrap().contents {
+                    CellContents::Empty(nb) => assert_eq!(nb, around, "cell {:?}", pos),
+                    CellContents::Mine(weight) => weights.push(weight),
+                }
+                let right = pos + (CHUNK_SIZE as i32, 0);
+                assert_eq!(number(&game.grid, right), number(&alone.grid, right), "cell {:?}", right);
+            }
+        }
+        for weight in [1, 2, 3, -1] {
+            assert!(weights.contains(&weight), "no mine of weight {}", weight);
+        }
+
+        // Flags go through the weights, then off
+        let pos = WorldPos::new(-5, -5);
+        for flag in [1, 2, 3, -1, 0] {
+            game.grid.flag(pos);
+            assert_eq!(game.grid.get_cell(pos).unwrap().flag, flag);
+        }
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let size = BoardSize::INTERMEDIATE;
+        let hidden = |game: &Game| {
+            (0..size.width as i32 * size.height as i32)
+                .filter(|i| !game.grid.get_cell(WorldPos::new(i % 16, i / 16)).unwrap().revealed)
+                .count()
+        };
+        let mut game = Game::new(Grid::fixed(4, size), Config::default());
+        game.reveal(WorldPos::new(8, 8));
+        let opened = game.grid.revealed_count();
+        assert!(opened > 1);
+
+        // The whole opening goes back at once
+        assert!(game.undo());
+        assert_eq!(hidden(&game), 256);
+        assert_eq!(game.grid.revealed_count(), 0);
+        assert!(game.redo());
+        assert_eq!(game.grid.revealed_count(), opened);
+        assert!(!game.redo());
+
+        let mine = (0..256).map(|i| WorldPos::new(i % 16, i / 16))
+            .find(|pos| game.grid.get_cell(*pos).unwrap().is_mine())
+            .unwrap();
+        game.flag(mine);
+        game.flag(mine);
+        let before = hidden(&game);
+        game.reveal(mine);
+        assert_eq!(game.state(), State::Lost);
+        assert!(hidden(&game) < before - 1);
+
+        // Losing is taken back, mines shown included
+        assert!(game.undo());
+        assert_eq!(game.state(), State::Playing);
+        assert_eq!(hidden(&game), before);
+        assert!(!game.grid.get_cell(mine).unwrap().revealed);
+        // Both flag toggles are steps of their own
+        assert!(game.undo());
+        assert!(game.grid.get_cell(mine).unwrap().is_flagged());
+
+        let config = Config { undo_losing_click: true, ..Config::default() };
+        let mut game = Game::new(Grid::fixed(4, size), config);
+        game.reveal(WorldPos::new(8, 8));
+        assert_eq!(game.reveal(mine).mines, 1);
+        assert_eq!(game.state(), State::Playing);
+        assert!(!game.grid.get_cell(mine).unwrap().revealed);
+        assert!(!game.can_redo());
+    }
+
+    #[test]
+    fn sparse_world_opens_as_it_is_explored() {
+        // Without mines, the first opening has no end
+        let config = Config { density: DensityCurve::flat(0.0), ..Config::default() };
+        let mut game = Game::new(Grid::new(2), config);
+        let view = ChunkRect::new(ChunkPos::new(-2, -2), ChunkPos::new(2, 2));
+        game.grid.update_chunks(view).unwrap();
+        game.reveal(WorldPos::new(0, 0));
+        assert!(game.grid.is_revealing());
+
+        // A click during the fill joins its step
+        game.flag(WorldPos::new(40, 40));
+        while game.grid.is_revealing() {
+            game.update();
+        }
+        assert_eq!(game.grid.revealed_count(), 25 * CHUNK_LEN as u64 - 1);
+        assert_eq!(game.grid.loaded_chunks().count(), 25);
+
+        let moved = ChunkRect::new(ChunkPos::new(-1, -2), ChunkPos::new(3, 2));
+        game.grid.update_chunks(moved).unwrap();
+        while game.grid.is_revealing() {
+            game.update();
+        }
+        assert_eq!(game.grid.revealed_count(), 30 * CHUNK_LEN as u64 - 1);
+
+        assert!(game.undo());
+        assert!(!game.can_undo());
+        assert_eq!(game.grid.revealed_count(), 0);
+        assert!(!game.grid.get_cell(WorldPos::new(40, 40)).unwrap().is_flagged());
+        game.grid.update_chunks(view).unwrap();
+        assert!(!game.grid.is_revealing());
+    }
+
+    #[test]
+    fn lives_carry_the_game_on() {
+        let size = BoardSize::INTERMEDIATE;
+        let config = Config { board: Some(size), lives: 3, ..Config::default() };
+        let mut game = Game::new(Grid::fixed(4, size), config);
+        game.reveal(WorldPos::new(8, 8));
+        let next_mine = |game: &Game| (0..256).map(|i| WorldPos::new(i % 16, i / 16))
+            .find(|pos| game.grid.get_cell(*pos).unwrap().is_mine() && !game.is_locked(*pos))
+            .unwrap();
+
+        let mine = next_mine(&game);
+        assert_eq!(game.reveal(mine).mines, 1);
+        assert_eq!((game.state(), game.lives()), (State::Playing, 2));
+        assert!(game.grid.get_cell(mine).unwrap().revealed);
+        // Cells around are out of reach
+        let near = mine + (1, 1);
+        assert!(game.is_locked(near));
+        game.flag(near);
+        assert!(game.grid.get_cell(near).is_none_or(|c| !c.is_flagged()));
+
+        assert!(game.undo());
+        assert_eq!(game.lives(), 3);
+        assert!(game.redo());
+        assert_eq!(game.lives(), 2);
+
+        let mine = next_mine(&game);
+        game.reveal(mine);
+        assert_eq!((game.state(), game.lives()), (State::Playing, 1));
+        let mine = next_mine(&game);
+        game.reveal(mine);
+        assert_eq!((game.state(), game.lives()), (State::Lost, 0));
+
+        // Casual games spend lives first, and only take back the last one
+        let config = Config { board: Some(size), lives: 2, undo_losing_click: true, ..Config::default() };
+        let mut game = Game::new(Grid::fixed(4, size), config);
+        game.reveal(WorldPos::new(8, 8));
+        let mine = next_mine(&game);
+        game.reveal(mine);
+        assert_eq!((game.state(), game.lives()), (State::Playing, 1));
+        assert!(game.grid.get_cell(mine).unwrap().revealed);
+        let mine = next_mine(&game);
+        game.reveal(mine);
+        assert_eq!((game.state(), game.lives()), (State::Playing, 1));
+        assert!(!game.grid.get_cell(mine).unwrap().revealed);
+    }
+
+    #[test]
+    fn lock_follows_the_cells() {
+        let config = Config { topology: TopologyKind::Hex, lock_radius: 1, ..Config::default() };
+        let game = Game::new(Grid::new(1), config);
+        let pos = WorldPos::new(3, 3);
+        let area = game.lock_area(pos);
+        assert_eq!(area.len(), 7);
+        assert!(game.grid.topology().neighbours(pos).iter().all(|p| area.contains(p)));
+
+        let config = Config { lock_radius: 2, ..Config::default() };
+        let game = Game::new(Grid::new(1), config);
+        assert_eq!(game.lock_area(pos).len(), 25);
+        let config = Config { neighbourhood: Neighbourhood::Cross, lock_radius: 2, ..Config::default() };
+        let game = Game::new(Grid::new(1), config);
+        assert_eq!(game.lock_area(pos).len(), 13);
+    }
+
+    #[test]
+    fn clearing_a_board_wins() {
+        let size = BoardSize::BEGINNER;
+        let mut game = Game::new(Grid::fixed(3, size), Config::default());
+        game.reveal(WorldPos::new(4, 4));
+        // Cells off the board don't exist
+        assert!(game.grid.get_cell(WorldPos::new(-1, 0)).is_none());
+        assert!(game.grid.get_cell(WorldPos::new(9, 0)).is_none());
+
+        let mut mines = 0;
+        for y in 0..size.height as i32 {
+            for x in 0..size.width as i32 {
+                let pos = WorldPos::new(x, y);
+                let cell = game.grid.get_cell(pos).unwrap();
+                if cell.is_mine() {
+                    mines += 1;
+                } else if !cell.revealed {
+                    assert_eq!(game.state(), State::Playing);
+                    game.reveal(pos);
+                }
+            }
+        }
+        assert_eq!(mines, size.mines);
+        assert_eq!(game.state(), State::Won);
+        assert!(game.grid.get_cell(WorldPos::new(4, 4)).unwrap().revealed);
+    }
+}
@@ -0,0 +1,866 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::*;
+
+/// Maximum number of cells a flood fill opens per call.
+pub const REVEAL_BUDGET: usize = 4096;
+
+/// Cells opened by a reveal, in the order they were opened.
+#[derive(Default)]
+pub struct Revealed {
+    pub cells: Vec<WorldPos>,
+    /// Mines among the cells revealed.
+    pub mines: u32,
+}
+
+impl Revealed {
+    pub fn append(&mut self, other: Revealed) {
+        self.cells.extend(other.cells);
+        self.mines += other.mines;
+    }
+}
+
+/// What cells looked like before a series of changes, to undo them.
+#[derive(Default, Debug)]
+pub(crate) struct Journal {
+    /// Revealed and flag, per cell changed.
+    cells: HashMap<WorldPos, (bool, i32)>,
+}
+
+impl Journal {
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+}
+
+/// The board, loaded chunk by chunk around the visible area.
+/// Either the infinite world, or a finite board whose chunks all stay loaded.
+pub struct Grid {
+    chunks: HashMap<ChunkPos, Chunk>,
+    /// Chunks kept loaded, as of the last `update_chunks`.
+    visible: ChunkRect,
+    seed: u64,
+    /// Area around the first click kept free of mines.
+    safe_zone: Option<WorldPos>,
+    /// Without a store, modified chunks are never evicted.
+    store: Option<ChunkStore>,
+    /// First error of the store since the last `update_views` or `save`, which return it.
+    error: Option<io::Error>,
+    /// Cells still to visit by the flood fill in progress.
+    pending: VecDeque<WorldPos>,
+    /// Cells of `pending`, so that none is queued twice.
+    queued: HashSet<WorldPos>,
+    /// Cells of the flood fill whose chunk isn't loaded, visited once it is. In a world sparse
+    /// enough for an opening to have no end, the fill stops at the loaded chunks this way.
+    parked: HashSet<WorldPos>,
+    /// Size of a finite board, `None` for the infinite world.
+    board: Option<BoardSize>,
+    /// Mines of a finite board, one per cell, row by row.
+    board_mines: Vec<bool>,
+    /// Safe cells revealed so far.
+    revealed: u64,
+    /// Time spent at most looking for a layout that needs no guess, `None` to place mines plainly.
+    no_guess: Option<Duration>,
+    /// Safe cells of a finite board that still need a guess, no layout without one being found in time.
+    unsolved: usize,
+    /// Mines per chunk of the infinite world.
+    density: DensityCurve,
+    /// How many mines each mined cell holds.
+    weights: MineWeights,
+    /// Which cells touch, for numbers, flood fills and chords.
+    topology: Box<dyn Topology>,
+    /// Changes since `start_journal`.
+    journal: Option<Journal>,
+    /// Chunks come from a server instead of being generated, see `Grid::remote`.
+    remote: bool,
+}
+
+impl Grid {
+    /// A world kept in memory only.
+    pub fn new(seed: u64) -> Grid {
+        Grid {
+            chunks: HashMap::new(),
+            visible: ChunkRect::new(ChunkPos::new(0, 0), ChunkPos::new(-1, -1)),
+            seed,
+            safe_zone: None,
+            store: None,
+            error: None,
+            pending: VecDeque::new(),
+            queued: HashSet::new(),
+            parked: HashSet::new(),
+            board: None,
+            board_mines: Vec::new(),
+            revealed: 0,
+            no_guess: None,
+            unsolved: 0,
+            density: DensityCurve::default(),
+            weights: MineWeights::default(),
+            topology: Box::new(Square::default()),
+            journal: None,
+            remote: false,
+        }
+    }
+
+    /// A finite board, kept in memory only.
+    pub fn fixed(seed: u64, size: BoardSize) -> Grid {
+        let mut result = Grid {
+            board: Some(size),
+            board_mines: size.mine_layout(seed, &[]),
+            ..Grid::new(seed)
+        };
+        for pos in size.chunks().iter() {
+            result.create_chunk(pos);
+        }
+        result
+    }
+
+    /// A finite board restored from all of its cells, row by row.
+    pub(crate) fn fixed_from_cells(seed: u64, size: BoardSize, safe_zone: Option<WorldPos>, cells: &[Cell]) -> Grid {
+        let mut result = Grid {
+            board: Some(size),
+            board_mines: cells.iter().map(|c| c.is_mine()).collect(),
+            safe_zone,
+            ..Grid::new(seed)
+        };
+        for pos in size.chunks().iter() {
+            result.create_chunk(pos);
+        }
+        for (i, saved) in cells.iter().enumerate() {
+            let pos = WorldPos::new((i as u32 % size.width) as i32, (i as u32 / size.width) as i32);
+            let cell = result.get_cell_mut(pos).unwrap();
+            cell.revealed = saved.revealed;
+            cell.flag = saved.flag;
+            if saved.revealed && !saved.is_mine() {
+                result.revealed += 1;
+            }
+        }
+        result
+    }
+
+    /// A world whose modified chunks are saved in `<save_dir>/<seed>/` when evicted.
+    pub fn persistent(seed: u64, save_dir: &Path) -> Grid {
+        let store = ChunkStore::new(&save_dir.join(seed.to_string()), seed);
+        let (info, error) = match store.load_info() {
+            Ok(info) => (info, None),
+            Err(e) => (WorldInfo::default(), Some(e)),
+        };
+        Grid {
+            safe_zone: info.safe_zone,
+            store: Some(store),
+            error,
+            ..Grid::new(seed)
+        }
+    }
+
+    /// A world whose chunks are received from a server with `insert_chunk`, its seed staying there.
+    /// Chunks not received yet are hidden and empty, and chunks out of view are dropped, modified or not.
+    pub fn remote() -> Grid {
+        Grid { remote: true, ..Grid::new(0) }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn safe_zone(&self) -> Option<WorldPos> {
+        self.safe_zone
+    }
+
+    pub fn board(&self) -> Option<BoardSize> {
+        self.board
+    }
+
+    /// Whether there is a cell at `pos`, always true in the infinite world.
+    pub fn contains(&self, pos: WorldPos) -> bool {
+        self.board.is_none_or(|b| b.contains(pos))
+    }
+
+    /// Number of safe cells revealed so far.
+    pub fn revealed_count(&self) -> u64 {
+        self.revealed
+    }
+
+    pub(crate) fn set_revealed_count(&mut self, revealed: u64) {
+        self.revealed = revealed;
+    }
+
+    /// Safe cells of a finite board placed without guess that need one all the same,
+    /// when no layout solved from the safe zone was found in time.
+    pub fn unsolved_cells(&self) -> usize {
+        self.unsolved
+    }
+
+    /// Only takes effect for chunks generated afterwards, and for finite boards once the safe zone is set.
+    pub fn set_no_guess(&mut self, budget: Option<Duration>) {
+        self.no_guess = budget;
+    }
+
+    /// Sets how many mines chunks of the infinite world get, before any is generated.
+    pub fn set_density(&mut self, density: DensityCurve) {
+        self.density = density;
+    }
+
+    /// Sets how many mines mined cells hold, weighing the mines of the loaded chunks again.
+    pub fn set_weights(&mut self, weights: MineWeights) {
+        self.weights = weights;
+        let seed = self.seed;
+        for chunk in self.chunks.values_mut() {
+            chunk.set_mines(chunk.mines(), |pos| weights.weight(seed, pos));
+        }
+        self.recount_loaded();
+    }
+
+    pub fn weights(&self) -> MineWeights {
+        self.weights
+    }
+
+    /// Sets which cells touch, counting the numbers of the loaded chunks again.
+    pub fn set_topology(&mut self, topology: Box<dyn Topology>) {
+        self.topology = topology;
+        self.recount_loaded();
+    }
+
+    fn recount_loaded(&mut self) {
+        let loaded: Vec<ChunkPos> = self.chunks.keys().copied().collect();
+        for c in loaded {
+            let mut chunk = self.chunks.remove(&c).unwrap();
+            self.count_neighbours(&mut chunk);
+            self.chunks.insert(c, chunk);
+        }
+    }
+
+    pub fn topology(&self) -> &dyn Topology {
+        &*self.topology
+    }
+
+    /// Cells kept free of mines: the safe zone and its neighbours.
+    fn safe_cells(&self) -> Vec<WorldPos> {
+        self.safe_zone.map_or(Vec::new(), |pos| self.topology.area(pos))
+    }
+
+    /// Whether every safe cell of a finite board is revealed.
+    pub fn is_cleared(&self) -> bool {
+        self.board.is_some() && self.revealed == self.board_mines.iter().filter(|m| !**m).count() as u64
+    }
+
+    /// Keeps `pos` and the cells around it free of mines. Meant for the first click, before
+    /// anything is revealed: the chunks overlapping that area are generated again, keeping their flags.
+    /// Failing to save it is told by the next `update_views` or `save`.
+    pub fn set_safe_zone(&mut self, pos: WorldPos) {
+        self.safe_zone = Some(pos);
+        if let Some(Err(e)) = self.store.as_ref().map(|s| s.save_info(&WorldInfo { safe_zone: self.safe_zone })) {
+            self.error.get_or_insert(e);
+        }
+        let affected: HashSet<ChunkPos> = match self.board {
+            Some(size) => {
+                // Mines of a finite board are placed all at once, so moving some moves them all
+                (self.board_mines, self.unsolved) = match self.no_guess {
+                    Some(budget) => no_guess::board_layout(size, self.seed, self.topology(), self.weights, pos, budget),
+                    None => (size.mine_layout(self.seed, &self.safe_cells()), 0),
+                };
+                self.chunks.keys().copied().collect()
+            },
+            None => self.safe_cells().iter().map(|p| p.chunk()).collect(),
+        };
+        for c in &affected {
+            self.get_or_create_chunk(*c);
+        }
+        for c in &affected {
+            let layout = match self.no_guess {
+                Some(budget) if self.board.is_none() => self.no_guess_layout(*c, &affected, budget).0,
+                _ => self.layout(*c),
+            };
+            let (no_guess, weights, seed) = (self.no_guess.is_some(), self.weights, self.seed);
+            let chunk = self.get_or_create_chunk(*c);
+            chunk.set_mines(layout, |pos| weights.weight(seed, pos));
+            if no_guess {
+                chunk.mark_modified();
+            }
+        }
+        self.recount_around(&affected);
+    }
+
+    /// Counts neighbours again in `changed` and the chunks around them.
+    fn recount_around(&mut self, changed: &HashSet<ChunkPos>) {
+        let around: HashSet<ChunkPos> = changed.iter()
+            .flat_map(|c| NEIGHBOURS.iter().map(move |o| *c + *o).chain([*c]))
+            .collect();
+        for c in around {
+            if let Some(mut chunk) = self.chunks.remove(&c) {
+                self.count_neighbours(&mut chunk);
+                self.chunks.insert(c, chunk);
+            }
+        }
+    }
+
+    /// Loads every chunk of `visible` and evicts the ones outside of it, see `update_views`.
+    pub fn update_chunks(&mut self, visible: ChunkRect) -> io::Result<()> {
+        self.visible = visible;
+        self.update_views(&[visible])
+    }
+
+    /// Loads every chunk of `views` and evicts the ones outside all of them,
+    /// e.g. for a server whose players each see their own part of the world.
+    /// A flood fill that was waiting for chunks now loaded carries on into them.
+    /// Modified chunks the store fails to take stay loaded, the first error of the store since the last call being returned.
+    pub fn update_views(&mut self, views: &[ChunkRect]) -> io::Result<()> {
+        if self.board.is_some() {
+            return Ok(());
+        }
+        let remote = self.remote;
+        let (store, error) = (&mut self.store, &mut self.error);
+        self.chunks.retain(|pos, chunk| {
+            let keep = views.iter().any(|v| v.contains(*pos));
+            if keep || !chunk.is_modified() || remote {
+                return keep;
+            }
+            match store.as_mut().map(|s| s.store(chunk)) {
+                Some(Ok(())) => false,
+                Some(Err(e)) => {
+                    error.get_or_insert(e);
+                    true
+                },
+                None => true,
+            }
+        });
+        for pos in views.iter().flat_map(|v| v.iter()) {
+            if !self.chunks.contains_key(&pos) {
+                self.create_chunk(pos);
+            }
+        }
+        let (chunks, pending, queued) = (&self.chunks, &mut self.pending, &mut self.queued);
+        self.parked.retain(|pos| {
+            let loaded = chunks.contains_key(&pos.chunk());
+            if loaded && queued.insert(*pos) {
+                pending.push_back(*pos);
+            }
+            !loaded
+        });
+        self.flush()
+    }
+
+    /// Loaded chunks of the visible area, row by row.
+    pub fn visible_chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.visible.iter().filter_map(|pos| self.chunks.get(&pos))
+    }
+
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.values()
+    }
+
+    /// Restores the chunk from the save directory, or generates it if it was never modified.
+    /// Without guess, where a chunk's mines are depends on the chunks generated before it,
+    /// so every chunk generated is kept as modified.
+    fn create_chunk(&mut self, chunk_coord: ChunkPos) {
+        if self.remote {
+            self.chunks.insert(chunk_coord, Chunk::new(chunk_coord, [false; CHUNK_LEN], |_| 0));
+            return;
+        }
+        if let Some(mut chunk) = self.load(chunk_coord) {
+            self.count_neighbours(&mut chunk);
+            self.chunks.insert(chunk_coord, chunk);
+            return;
+        }
+        let (layout, rerolled) = match self.no_guess {
+            Some(budget) if self.board.is_none() => self.no_guess_layout(chunk_coord, &HashSet::new(), budget),
+            _ => (self.layout(chunk_coord), false),
+        };
+        let mut chunk = Chunk::new(chunk_coord, layout, |pos| self.weights.weight(self.seed, pos));
+        if self.no_guess.is_some() {
+            chunk.mark_modified();
+        }
+        self.count_neighbours(&mut chunk);
+        self.chunks.insert(chunk_coord, chunk);
+        if rerolled {
+            // Neighbours were counted with the plain layout
+            self.recount_around(&HashSet::from([chunk_coord]));
+        }
+    }
+
+    /// Layout of a chunk of the infinite world that needs no guess,
+    /// with the chunks of `ignored` taken as not generated yet.
+    fn no_guess_layout(&mut self, chunk_coord: ChunkPos, ignored: &HashSet<ChunkPos>, budget: Duration) -> ([bool; CHUNK_LEN], bool) {
+        let mut around = no_guess::Surroundings { known: HashMap::new(), unknown: HashMap::new() };
+        for o in NEIGHBOURS {
+            let c = chunk_coord + o;
+            match self.generated_cells(c).filter(|_| !ignored.contains(&c)) {
+                Some(cells) => { around.known.insert(c, cells); },
+                None => { around.unknown.insert(c, self.layout(c)); },
+            }
+        }
+        let mines = self.density.mines(chunk_coord, self.seed);
+        no_guess::chunk_layout(chunk_coord, mines, self, &around, budget)
+    }
+
+    /// Cells of a chunk loaded or saved.
+    fn generated_cells(&mut self, chunk_coord: ChunkPos) -> Option<[Cell; CHUNK_LEN]> {
+        let chunk = match self.chunks.get(&chunk_coord) {
+            Some(chunk) => chunk,
+            None => &self.load(chunk_coord)?,
+        };
+        let mut result = [Cell::default(); CHUNK_LEN];
+        for (cell, (_, c)) in result.iter_mut().zip(chunk.cells()) {
+            *cell = *c;
+        }
+        Some(result)
+    }
+
+    /// Mines of the chunk as generated.
+    fn layout(&self, chunk_coord: ChunkPos) -> [bool; CHUNK_LEN] {
+        let Some(size) = self.board else {
+            let mines = self.density.mines(chunk_coord, self.seed);
+            return Chunk::mine_layout(chunk_coord, mines, self.seed, &self.safe_cells());
+        };
+        let mut result = [false; CHUNK_LEN];
+        for (i, mine) in result.iter_mut().enumerate() {
+            let pos = chunk_coord.world(LocalPos::from_idx(i));
+            if size.contains(pos) {
+                *mine = self.board_mines[(pos.x as u32 + pos.y as u32 * size.width) as usize];
+            }
+        }
+        result
+    }
+
+    /// Mines of the chunk, whether it is loaded, saved or not generated yet.
+    pub(crate) fn mine_layout(&mut self, chunk_coord: ChunkPos) -> [bool; CHUNK_LEN] {
+        if let Some(c) = self.chunks.get(&chunk_coord) {
+            return c.mines();
+        }
+        match self.load(chunk_coord) {
+            Some(c) => c.mines(),
+            None => self.layout(chunk_coord),
+        }
+    }
+
+    /// Fills in the numbers of `chunk` from its own mines and from those of the 8 chunks around it.
+    /// Neighbours that aren't loaded are derived from the seed, so a border is counted the same way
+    /// whichever side of it gets generated first. Numbers of a finite board are counted from
+    /// the mines of the whole board, as its neighbours may wrap around to the other side.
+    fn count_neighbours(&mut self, chunk: &mut Chunk) {
+        // Numbers of a remote world come with its chunks, and hidden cells don't tell theirs
+        if self.remote {
+            return;
+        }
+        let mut layouts = [[false; CHUNK_LEN]; 9];
+        if self.board.is_none() {
+            for (i, layout) in layouts.iter_mut().enumerate() {
+                let offset = (i as i32 % 3 - 1, i as i32 / 3 - 1);
+                *layout = if offset == (0, 0) {
+                    chunk.mines()
+                } else {
+                    self.mine_layout(chunk.position() + offset)
+                };
+            }
+        }
+        let position = chunk.position();
+        let is_mine = |pos: WorldPos| match self.board {
+            Some(size) => size.contains(pos) && self.board_mines[(pos.x as u32 + pos.y as u32 * size.width) as usize],
+            None => {
+                let c = pos.chunk();
+                let layout = (c.y - position.y + 1) * 3 + c.x - position.x + 1;
+                layouts[layout as usize][pos.local().idx()]
+            },
+        };
+        // Weights come from the seed too, for the same reason as layouts
+        let weight = |pos: WorldPos| if is_mine(pos) { self.weights.weight(self.seed, pos) } else { 0 };
+        for (local, cell) in chunk.cells_mut() {
+            let pos = position.world(local);
+            if let CellContents::Empty(ref mut nb) = cell.contents {
+                *nb = self.topology.neighbours(pos).into_iter().map(weight).sum();
+            }
+        }
+    }
+
+    /// Writes every modified chunk to the save directory, loaded or not.
+    /// Returns the first error of the store since the last `update_views` or `save`.
+    pub fn save(&mut self) -> io::Result<()> {
+        if let Some(store) = &mut self.store {
+            for c in self.chunks.values().filter(|c| c.is_modified()) {
+                if let Err(e) = store.store(c) {
+                    self.error.get_or_insert(e);
+                }
+            }
+        }
+        self.flush()
+    }
+
+    /// Writes the regions changed, returning the first error since the last flush.
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(Err(e)) = self.store.as_mut().map(|s| s.flush()) {
+            self.error.get_or_insert(e);
+        }
+        self.error.take().map_or(Ok(()), Err)
+    }
+
+    /// Chunk saved at `chunk_coord`, keeping the error if the store fails to read it.
+    fn load(&mut self, chunk_coord: ChunkPos) -> Option<Chunk> {
+        match self.store.as_mut()?.load(chunk_coord) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                self.error.get_or_insert(e);
+                None
+            },
+        }
+    }
+
+    /// Cell at `pos`, if its chunk is loaded.
+    pub fn get_cell(&self, pos: WorldPos) -> Option<&Cell> {
+        if !self.contains(pos) {
+            return None;
+        }
+        let chunk = self.chunks.get(&pos.chunk())?;
+        Some(chunk.get_cell(pos.local()))
+    }
+
+    pub fn get_cell_mut(&mut self, pos: WorldPos) -> Option<&mut Cell> {
+        if !self.contains(pos) {
+            return None;
+        }
+        let chunk = self.chunks.get_mut(&pos.chunk())?;
+        Some(chunk.get_cell_mut(pos.local()))
+    }
+
+    fn get_or_create_chunk(&mut self, chunk_coord: ChunkPos) -> &mut Chunk {
+        if !self.chunks.contains_key(&chunk_coord) {
+            self.create_chunk(chunk_coord);
+        }
+        self.chunks.get_mut(&chunk_coord).unwrap()
+    }
+
+    /// The chunk at `chunk_coord`, generated or restored if it isn't loaded.
+    pub fn chunk_or_create(&mut self, chunk_coord: ChunkPos) -> &Chunk {
+        self.get_or_create_chunk(chunk_coord)
+    }
+
+    /// Puts in `chunk` as it is, neighbour counts included, e.g. a chunk received from a server.
+    pub fn insert_chunk(&mut self, chunk: Chunk) {
+        self.chunks.insert(chunk.position(), chunk);
+    }
+
+    /// Like `get_cell`, but generates or restores the chunk if it isn't loaded.
+    pub fn cell_or_create(&mut self, pos: WorldPos) -> Option<&Cell> {
+        if !self.contains(pos) {
+            return None;
+        }
+        Some(self.get_or_create_chunk(pos.chunk()).get_cell(pos.local()))
+    }
+
+    /// Like `get_cell_mut`, but generates or restores the chunk if it isn't loaded.
+    pub fn get_cell_or_create(&mut self, pos: WorldPos) -> Option<&mut Cell> {
+        if !self.contains(pos) {
+            return None;
+        }
+        Some(self.get_or_create_chunk(pos.chunk()).get_cell_mut(pos.local()))
+    }
+
+    /// Starts keeping track of the cells that change, replacing the journal in progress.
+    pub(crate) fn start_journal(&mut self) {
+        self.journal = Some(Journal { cells: HashMap::new() });
+    }
+
+    pub(crate) fn take_journal(&mut self) -> Option<Journal> {
+        self.journal.take()
+    }
+
+    /// Puts back the cells as they were when `journal` was started. The flood fill stops next to
+    /// the cells hidden again, while the parts of it started before the journal carry on.
+    pub(crate) fn undo(&mut self, journal: &Journal) {
+        for (pos, (revealed, flag)) in &journal.cells {
+            let Some(cell) = self.get_cell_or_create(*pos) else {
+                continue;
+            };
+            let hidden = cell.revealed && !*revealed && !cell.is_mine();
+            cell.revealed = *revealed;
+            cell.flag = *flag;
+            if hidden {
+                self.revealed -= 1;
+            }
+        }
+        let topology = &self.topology;
+        let hidden = |pos: &WorldPos| topology.neighbours(*pos).iter().any(|p| journal.cells.get(p).is_some_and(|(r, _)| !r));
+        self.pending.retain(|pos| !hidden(pos));
+        self.parked.retain(|pos| !hidden(pos));
+        self.queued = self.pending.iter().copied().collect();
+    }
+
+    /// Keeps the cell at `pos` as it is now in the journal, unless it changed already.
+    fn note(&mut self, pos: WorldPos) {
+        let Some(cell) = self.get_cell(pos).copied() else {
+            return;
+        };
+        if let Some(journal) = &mut self.journal {
+            journal.cells.entry(pos).or_insert((cell.revealed, cell.flag));
+        }
+    }
+
+    /// Reveals the cell at `pos`. If it has no mine around it, the whole opening is flood filled,
+    /// `REVEAL_BUDGET` cells at once, the rest being left for `continue_reveal`.
+    pub fn reveal(&mut self, pos: WorldPos) -> Revealed {
+        let mut result = Revealed::default();
+        let Some(cell) = self.cell_or_create(pos).copied() else {
+            return result;
+        };
+        if cell.revealed || cell.is_flagged() {
+            return result;
+        }
+        self.note(pos);
+        self.get_cell_or_create(pos).unwrap().revealed = true;
+        result.cells.push(pos);
+        match cell.contents {
+            CellContents::Mine(_) => result.mines += 1,
+            CellContents::Empty(nb) => {
+                self.revealed += 1;
+                if self.is_opening(pos, nb) {
+                    self.queue_around(pos);
+                    result.cells.extend(self.continue_reveal(REVEAL_BUDGET).cells);
+                }
+            },
+        }
+        result
+    }
+
+    pub fn is_revealing(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Cells the flood fill in progress is still to visit, whether their chunk is loaded or not.
+    pub(crate) fn pending(&self) -> Vec<WorldPos> {
+        self.pending.iter().chain(&self.parked).copied().collect()
+    }
+
+    /// Carries on with a flood fill left with `cells` to visit, e.g. by a saved game.
+    pub(crate) fn set_pending(&mut self, cells: &[WorldPos]) {
+        self.pending = cells.iter().copied().collect();
+        self.queued = cells.iter().copied().collect();
+        self.parked.clear();
+    }
+
+    /// Carries on with the current flood fill, revealing at most `budget` cells.
+    /// Cells of the infinite world whose chunk isn't loaded wait for `update_views` to load it.
+    pub fn continue_reveal(&mut self, budget: usize) -> Revealed {
+        let mut result = Revealed::default();
+        while result.cells.len() < budget {
+            let Some(pos) = self.pending.pop_front() else {
+                break;
+            };
+            self.queued.remove(&pos);
+            if self.board.is_none() && !self.chunks.contains_key(&pos.chunk()) {
+                self.parked.insert(pos);
+                continue;
+            }
+            let Some(cell) = self.cell_or_create(pos).copied() else {
+                continue;
+            };
+            // Neighbours of an opening can't be mines, so only flags stop the fill
+            if cell.revealed || cell.is_flagged() {
+                continue;
+            }
+            self.note(pos);
+            self.get_cell_or_create(pos).unwrap().revealed = true;
+            result.cells.push(pos);
+            self.revealed += 1;
+            if cell.contents == CellContents::Empty(0) && self.is_opening(pos, 0) {
+                self.queue_around(pos);
+            }
+        }
+        result
+    }
+
+    /// Queues the neighbours of the opening at `pos` for the flood fill, unless they are revealed or queued already.
+    fn queue_around(&mut self, pos: WorldPos) {
+        for p in self.topology.neighbours(pos) {
+            if self.get_cell(p).is_some_and(|c| c.revealed) || !self.queued.insert(p) {
+                continue;
+            }
+            self.pending.push_back(p);
+        }
+    }
+
+    /// Whether the cell at `pos`, showing `nb`, has no mine around it. A 0 may still
+    /// be next to mines when negative mines cancel out others.
+    fn is_opening(&mut self, pos: WorldPos, nb: i32) -> bool {
+        if nb != 0 {
+            return false;
+        }
+        self.weights.negative == 0.0 || self.topology.neighbours(pos).into_iter().all(|p| !self.is_mine(p))
+    }
+
+    /// Whether there is a mine at `pos`, without loading its chunk.
+    fn is_mine(&mut self, pos: WorldPos) -> bool {
+        match self.get_cell(pos) {
+            Some(cell) => cell.is_mine(),
+            None => self.contains(pos) && self.mine_layout(pos.chunk())[pos.local().idx()],
+        }
+    }
+
+    /// Reveals every unflagged neighbour of the number at `pos`, provided the flags around add up to
+    /// the number, counting each flag for the mines it marks. If a flag was wrong, one of the revealed cells is a mine.
+    pub fn chord(&mut self, pos: WorldPos) -> Revealed {
+        let mut result = Revealed::default();
+        let Some(cell) = self.get_cell(pos) else {
+            return result;
+        };
+        let CellContents::Empty(nb) = cell.contents else {
+            return result;
+        };
+        if !cell.revealed {
+            return result;
+        }
+        let around = self.topology.neighbours(pos);
+        let flags: Vec<i32> = around.iter()
+            .filter_map(|p| self.cell_or_create(*p).map(|c| c.flag))
+            .filter(|f| *f != 0)
+            .collect();
+        if flags.is_empty() || flags.iter().sum::<i32>() != nb {
+            return result;
+        }
+        for p in around {
+            result.append(self.reveal(p));
+        }
+        result
+    }
+
+    /// Puts the next flag on the cell at `pos`: a single mine, then the other weights mines can have, then none.
+    pub fn flag(&mut self, pos: WorldPos) {
+        if self.get_cell(pos).is_some_and(|c| !c.revealed) {
+            self.note(pos);
+            let weights = self.weights;
+            let cell = self.get_cell_mut(pos).unwrap();
+            cell.flag = weights.next_flag(cell.flag);
+        }
+    }
+
+    pub fn show_all_mines(&mut self) {
+        self.note_loaded(|c| c.is_mine() && !c.revealed);
+        for c in self.chunks.values_mut() {
+            c.show_all_mines();
+        }
+    }
+
+    /// Flags every loaded mine, e.g. once a board is cleared.
+    pub fn flag_all_mines(&mut self) {
+        self.note_loaded(|c| matches!(c.contents, CellContents::Mine(w) if !c.revealed && c.flag != w));
+        for c in self.chunks.values_mut() {
+            c.flag_all_mines();
+        }
+    }
+
+    /// Notes every loaded cell about to change, when keeping a journal.
+    fn note_loaded(&mut self, changing: impl Fn(&Cell) -> bool) {
+        if self.journal.is_none() {
+            return;
+        }
+        let changed: Vec<WorldPos> = self.chunks.values()
+            .flat_map(|chunk| chunk.cells()
+                .filter(|(_, c)| changing(c))
+                .map(|(local, _)| chunk.position().world(local)))
+            .collect();
+        for pos in changed {
+            self.note(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn counts_cross_chunk_borders() {
+        let seed = 3;
+        let mines = |c: ChunkPos| DensityCurve::default().mines(c, seed);
+        let is_mine = |pos: WorldPos| Chunk::mine_layout(pos.chunk(), mines(pos.chunk()), seed, &[])[pos.local().idx()];
+
+        let mut grid = Grid::new(seed);
+        grid.create_chunk(ChunkPos::new(-1, 0));
+        let chunk = &grid.chunks[&ChunkPos::new(-1, 0)];
+        for (local, cell) in chunk.cells() {
+            let pos = chunk.position().world(local);
+            if let CellContents::Empty(nb) = cell.contents {
+                let expected = NEIGHBOURS.iter().filter(|o| is_mine(pos + *o)).count() as i32;
+                assert_eq!(nb, expected, "cell {:?}", pos);
+            }
+        }
+    }
+
+    #[test]
+    fn reveal_opens_whole_region() {
+        let mut grid = Grid::new(11);
+        // The fill waits at the loaded chunks, which are enough for the opening
+        grid.update_chunks(ChunkRect::new(ChunkPos::new(-2, -2), ChunkPos::new(2, 2))).unwrap();
+        let chunk = &grid.chunks[&ChunkPos::new(0, 0)];
+        let start = chunk.cells()
+            .find(|(_, c)| c.contents == CellContents::Empty(0))
+            .map(|(local, _)| chunk.position().world(local))
+            .unwrap();
+
+        let mut revealed = grid.reveal(start).cells;
+        while grid.is_revealing() {
+            // Cells are queued once
+            assert_eq!(grid.pending.len(), grid.queued.len());
+            revealed.extend(grid.continue_reveal(16).cells);
+        }
+        assert!(revealed.len() > 1);
+        for pos in revealed {
+            let cell = grid.get_cell(pos).unwrap();
+            assert!(cell.revealed);
+            if let CellContents::Empty(0) = cell.contents {
+                for o in NEIGHBOURS {
+                    assert!(grid.get_cell(pos + o).unwrap().revealed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn looking_leaves_chunks_unmodified() {
+        let mut grid = Grid::new(11);
+        grid.update_chunks(ChunkRect::new(ChunkPos::new(-1, -1), ChunkPos::new(1, 1))).unwrap();
+        // A number on the right edge of the chunk at the origin, whose chord reads the chunk next to it
+        let pos = (0..CHUNK_SIZE as i32).map(|y| WorldPos::new(CHUNK_SIZE as i32 - 1, y))
+            .find(|p| matches!(grid.get_cell(*p).unwrap().contents, CellContents::Empty(n) if n > 0))
+            .unwrap();
+        grid.reveal(pos);
+        grid.chord(pos);
+        assert!(grid.chunks[&ChunkPos::new(0, 0)].is_modified());
+        assert!(!grid.chunks[&ChunkPos::new(1, 0)].is_modified());
+    }
+
+    #[test]
+    fn chord_reveals_around_satisfied_number() {
+        let mut grid = Grid::new(11);
+        let number = |grid: &mut Grid| {
+            let mut pos = WorldPos::new(0, 0);
+            while !matches!(grid.get_cell_or_create(pos).unwrap().contents, CellContents::Empty(n) if n > 0) {
+                pos = pos + (1, 0);
+            }
+            pos
+        };
+
+        let pos = number(&mut grid);
+        grid.reveal(pos);
+        for o in NEIGHBOURS {
+            if grid.get_cell_or_create(pos + o).unwrap().is_mine() {
+                grid.flag(pos + o);
+            }
+        }
+        assert_eq!(grid.chord(pos).mines, 0);
+        for o in NEIGHBOURS {
+            let cell = grid.get_cell(pos + o).unwrap();
+            assert!(cell.revealed != cell.is_flagged());
+        }
+
+        // Same number with the flags moved to safe cells
+        let mut grid = Grid::new(11);
+        grid.reveal(pos);
+        let CellContents::Empty(nb) = grid.get_cell(pos).unwrap().contents else { unreachable!() };
+        let safe: Vec<_> = NEIGHBOURS.iter().filter(|o| !grid.get_cell_or_create(pos + *o).unwrap().is_mine()).collect();
+        for o in safe.iter().take(nb as usize) {
+            grid.flag(pos + *o);
+        }
+        assert!(grid.chord(pos).mines > 0);
+    }
+}
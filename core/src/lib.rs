@@ -0,0 +1,63 @@
+//! Rules of the infinite minesweeper: board model, generation and player actions.
+//! Nothing in here knows about windows, input or rendering.
+
+mod codec;
+
+mod rng;
+pub use rng::*;
+
+mod coords;
+pub use coords::*;
+
+mod cell;
+pub use cell::*;
+
+mod chunk;
+pub use chunk::*;
+
+mod store;
+use store::{ChunkStore, WorldInfo};
+
+mod board;
+pub use board::*;
+
+mod neighbourhood;
+pub use neighbourhood::*;
+
+mod topology;
+pub use topology::*;
+
+mod density;
+pub use density::*;
+
+mod weights;
+pub use weights::*;
+
+mod grid;
+pub use grid::*;
+
+mod solver;
+pub use solver::*;
+
+mod no_guess;
+
+mod config;
+pub use config::*;
+
+mod game;
+pub use game::*;
+
+mod replay;
+pub use replay::*;
+
+mod save;
+pub use save::*;
+
+mod metrics;
+pub use metrics::*;
+
+mod stats;
+pub use stats::*;
+
+mod net;
+pub use net::*;
@@ -0,0 +1,164 @@
+//! Standard measures of how hard a layout is, to compare generators and score games.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::*;
+
+/// Measures of a rectangle of cells.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Bechtel's Board Benchmark Value: the fewest clicks clearing the area without flagging,
+    /// one per opening plus one per number that no opening reveals.
+    pub bbbv: u32,
+    /// Cells revealed by each opening, numbers at its edge included, the largest first.
+    /// An opening is a connected region of cells with no mine around them.
+    pub openings: Vec<u32>,
+    /// Connected groups of numbers that no opening reveals, each cleared a click per cell.
+    pub islands: u32,
+    pub mines: u32,
+    pub cells: u32,
+}
+
+impl Metrics {
+    /// Metrics of a finite board, `None` for the infinite world.
+    pub fn of_board(grid: &Grid) -> Option<Metrics> {
+        let size = grid.board()?;
+        let is_mine = |pos: WorldPos| grid.get_cell(pos).is_some_and(|c| c.is_mine());
+        let max = WorldPos::new(size.width as i32 - 1, size.height as i32 - 1);
+        Some(Metrics::compute(WorldPos::new(0, 0), max, grid.topology(), is_mine))
+    }
+
+    /// Metrics of the cells from `min` to `max` included, as if they were a board of their own,
+    /// though numbers still count the mines outside. Chunks not generated yet are laid out
+    /// by the plain generator, which may differ from what the no guess mode would place.
+    pub fn of_region(grid: &mut Grid, min: WorldPos, max: WorldPos) -> Metrics {
+        // Neighbours reach further than the next chunk with some shapes and rules, e.g. triangles
+        let topology = grid.topology();
+        let chunks: HashSet<ChunkPos> = (min.y..=max.y)
+            .flat_map(|y| (min.x..=max.x).map(move |x| WorldPos::new(x, y)))
+            .flat_map(|pos| topology.area(pos))
+            .map(|pos| pos.chunk())
+            .collect();
+        let mut layouts = HashMap::new();
+        for c in chunks {
+            layouts.insert(c, grid.mine_layout(c));
+        }
+        Metrics::compute(min, max, grid.topology(), |pos| layouts[&pos.chunk()][pos.local().idx()])
+    }
+
+    fn compute(min: WorldPos, max: WorldPos, topology: &dyn Topology, is_mine: impl Fn(WorldPos) -> bool) -> Metrics {
+        let inside = |pos: WorldPos| pos.x >= min.x && pos.y >= min.y && pos.x <= max.x && pos.y <= max.y;
+        let cells = (min.y..=max.y).flat_map(|y| (min.x..=max.x).map(move |x| WorldPos::new(x, y)));
+        let is_zero = |pos: WorldPos| !is_mine(pos) && topology.neighbours(pos).into_iter().all(|p| !is_mine(p));
+        let mut result = Metrics::default();
+
+        let mut opened = HashSet::new();
+        for pos in cells.clone() {
+            result.cells += 1;
+            if is_mine(pos) {
+                result.mines += 1;
+            }
+            if !is_zero(pos) || opened.contains(&pos) {
+                continue;
+            }
+            let mut size = 0;
+            let mut pending = vec![pos];
+            while let Some(pos) = pending.pop() {
+                if !inside(pos) || !opened.insert(pos) {
+                    continue;
+                }
+                size += 1;
+                if is_zero(pos) {
+                    pending.extend(topology.neighbours(pos));
+                }
+            }
+            result.openings.push(size);
+        }
+        result.openings.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut island = HashSet::new();
+        let lone = |pos: WorldPos| inside(pos) && !is_mine(pos) && !opened.contains(&pos);
+        for pos in cells.clone().filter(|p| lone(*p)) {
+            result.bbbv += 1;
+            if island.contains(&pos) {
+                continue;
+            }
+            result.islands += 1;
+            let mut pending = vec![pos];
+            while let Some(pos) = pending.pop() {
+                if lone(pos) && island.insert(pos) {
+                    pending.extend(topology.neighbours(pos));
+                }
+            }
+        }
+        result.bbbv += result.openings.len() as u32;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn board(width: u32, height: u32, mines: &[usize]) -> Grid {
+        let mut cells = vec![Cell::default(); (width * height) as usize];
+        for i in mines {
+            cells[*i].contents = CellContents::Mine(1);
+        }
+        let size = BoardSize { width, height, mines: mines.len() as u32 };
+        Grid::fixed_from_cells(1, size, None, &cells)
+    }
+
+    #[test]
+    fn small_boards() {
+        // Mines in the corners: one opening in the middle reveals every number
+        let metrics = Metrics::of_board(&board(5, 5, &[0, 4, 20, 24])).unwrap();
+        assert_eq!(metrics, Metrics { bbbv: 1, openings: vec![21], islands: 0, mines: 4, cells: 25 });
+
+        // A lone mine in the middle: no opening, a click per number
+        let metrics = Metrics::of_board(&board(3, 3, &[4])).unwrap();
+        assert_eq!((metrics.bbbv, metrics.openings.len(), metrics.islands), (8, 0, 1));
+
+        // A wall of mines splits the board in two openings
+        // . . . * . . .
+        // . . . * . . .
+        // . . . * . . .
+        // . . . * . . .
+        // * * * * * * *
+        let mines = [3, 10, 17, 24, 28, 29, 30, 31, 32, 33, 34];
+        let metrics = Metrics::of_board(&board(7, 5, &mines)).unwrap();
+        assert_eq!(metrics.openings, vec![12, 12]);
+        assert_eq!((metrics.bbbv, metrics.islands), (2, 0));
+    }
+
+    #[test]
+    fn region_of_the_infinite_world() {
+        let mut grid = Grid::new(3);
+        let (min, max) = (WorldPos::new(-20, -20), WorldPos::new(19, 19));
+        let metrics = Metrics::of_region(&mut grid, min, max);
+        assert_eq!(metrics.cells, 1600);
+        assert!(metrics.bbbv >= metrics.openings.len() as u32 + metrics.islands);
+
+        // Same layout once loaded
+        grid.update_chunks(ChunkRect::new((min + (-1, -1)).chunk(), (max + (1, 1)).chunk())).unwrap();
+        assert_eq!(Metrics::of_region(&mut grid, min, max), metrics);
+    }
+
+    #[test]
+    fn region_next_to_far_neighbours() {
+        // Both reach 2 cells away, into the chunks left of and above the region
+        let topologies = [
+            TopologyKind::Triangle.build(None, &Neighbourhood::Classic, 1),
+            TopologyKind::Square.build(None, &Neighbourhood::Extended, 1),
+        ];
+        for topology in topologies {
+            let mut grid = Grid::new(3);
+            grid.set_topology(topology);
+            let (min, max) = (WorldPos::new(1, 1), WorldPos::new(10, 10));
+            let metrics = Metrics::of_region(&mut grid, min, max);
+            assert_eq!(metrics.cells, 100);
+            grid.update_chunks(ChunkRect::new(WorldPos::new(-1, -1).chunk(), max.chunk())).unwrap();
+            assert_eq!(Metrics::of_region(&mut grid, min, max), metrics);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::*;
+
+const CROSS: [(i32, i32); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+
+const KNIGHT: [(i32, i32); 8] = [
+    (-1, -2), (1, -2),
+    (-2, -1), (2, -1),
+    (-2, 1), (2, 1),
+    (-1, 2), (1, 2),
+];
+
+const EXTENDED: [(i32, i32); 24] = [
+    (-2, -2), (-1, -2), (0, -2), (1, -2), (2, -2),
+    (-2, -1), (-1, -1), (0, -1), (1, -1), (2, -1),
+    (-2, 0), (-1, 0), (1, 0), (2, 0),
+    (-2, 1), (-1, 1), (0, 1), (1, 1), (2, 1),
+    (-2, 2), (-1, 2), (0, 2), (1, 2), (2, 2),
+];
+
+/// Which cells around a square cell count in its number. Flood fills open the same cells
+/// around an empty cell, and chords reveal them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Neighbourhood {
+    /// The 8 cells around.
+    #[default]
+    Classic,
+    /// The 4 cells sharing a side.
+    Cross,
+    /// The 8 cells a knight's move away.
+    Knight,
+    /// The 24 cells up to two away, a 5x5 square.
+    Extended,
+    /// Offsets given by the player, sorted row by row.
+    Custom(Vec<(i32, i32)>),
+}
+
+impl Neighbourhood {
+    /// Farthest a custom neighbour can be, in either coordinate.
+    pub const MAX_REACH: i32 = 3;
+
+    /// A custom neighbourhood, from offsets in any order. Neighbours go both ways, so the cell opposite
+    /// each offset has to be counted too, as locks and flood fills take for granted.
+    pub fn custom(mut offsets: Vec<(i32, i32)>) -> Result<Neighbourhood, String> {
+        offsets.sort_by_key(|(x, y)| (*y, *x));
+        offsets.dedup();
+        if offsets.is_empty() {
+            return Err("a neighbourhood needs at least one cell".to_string());
+        }
+        if offsets.contains(&(0, 0)) {
+            return Err("a cell can't be its own neighbour".to_string());
+        }
+        if offsets.iter().any(|(x, y)| x.abs() > Self::MAX_REACH || y.abs() > Self::MAX_REACH) {
+            return Err(format!("neighbours must be at most {} cells away", Self::MAX_REACH));
+        }
+        if let Some((x, y)) = offsets.iter().find(|(x, y)| !offsets.contains(&(-x, -y))) {
+            return Err(format!("the neighbour at {},{} needs the one at {},{} opposite", x, y, -x, -y));
+        }
+        Ok(Neighbourhood::Custom(offsets))
+    }
+
+    pub fn offsets(&self) -> &[(i32, i32)] {
+        match self {
+            Neighbourhood::Classic => &NEIGHBOURS,
+            Neighbourhood::Cross => &CROSS,
+            Neighbourhood::Knight => &KNIGHT,
+            Neighbourhood::Extended => &EXTENDED,
+            Neighbourhood::Custom(offsets) => offsets,
+        }
+    }
+}
+
+/// Writes the name of a preset, or the mask of a custom neighbourhood as parsed by `from_str`.
+impl fmt::Display for Neighbourhood {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Neighbourhood::Classic => write!(f, "classic"),
+            Neighbourhood::Cross => write!(f, "cross"),
+            Neighbourhood::Knight => write!(f, "knight"),
+            Neighbourhood::Extended => write!(f, "extended"),
+            Neighbourhood::Custom(offsets) => {
+                let reach = offsets.iter().map(|(x, y)| x.abs().max(y.abs())).max().unwrap_or(0);
+                for y in -reach..=reach {
+                    if y != -reach {
+                        write!(f, "/")?;
+                    }
+                    for x in -reach..=reach {
+                        let c = if (x, y) == (0, 0) { 'o' } else if offsets.contains(&(x, y)) { 'x' } else { '.' };
+                        write!(f, "{}", c)?;
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Parses `classic`, `cross`, `knight`, `extended`, or a mask: rows of the same odd length separated
+/// by `/` or new lines, an odd number of them, with `x` for the cells counted, `.` for the others
+/// and `o` or `.` for the cell itself in the middle. `x.x/.o./x.x` counts the 4 diagonal cells.
+/// Masks are the same turned half a turn, see `Neighbourhood::custom`.
+impl FromStr for Neighbourhood {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Neighbourhood, String> {
+        match s {
+            "classic" => return Ok(Neighbourhood::Classic),
+            "cross" => return Ok(Neighbourhood::Cross),
+            "knight" => return Ok(Neighbourhood::Knight),
+            "extended" => return Ok(Neighbourhood::Extended),
+            _ => {},
+        }
+        let invalid = || format!("invalid neighbourhood '{}', expected a preset or a mask like x.x/.o./x.x", s);
+        let rows: Vec<&str> = s.split(['/', '\n']).map(str::trim).filter(|r| !r.is_empty()).collect();
+        let height = rows.len() as i32;
+        let width = rows.first().map_or(0, |r| r.len()) as i32;
+        if height % 2 == 0 || width % 2 == 0 || rows.iter().any(|r| r.len() as i32 != width) {
+            return Err(invalid());
+        }
+        let mut offsets = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let offset = (x as i32 - width / 2, y as i32 - height / 2);
+                match c {
+                    'x' => offsets.push(offset),
+                    'o' if offset == (0, 0) => {},
+                    '.' => {},
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+        Neighbourhood::custom(offsets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn parse_masks() {
+        let diagonals: Neighbourhood = "x.x/.o./x.x".parse().unwrap();
+        assert_eq!(diagonals, Neighbourhood::Custom(vec![(-1, -1), (1, -1), (-1, 1), (1, 1)]));
+        assert_eq!(diagonals.to_string(), "x.x/.o./x.x");
+        // Rows on lines, the mask being trimmed to the farthest neighbour when written
+        let far: Neighbourhood = "....x\n.....\n..o..\n.....\nx....".parse().unwrap();
+        assert_eq!(far.offsets(), &[(2, -2), (-2, 2)]);
+        assert_eq!(far.to_string().parse(), Ok(far));
+        assert_eq!("knight".parse(), Ok(Neighbourhood::Knight));
+        assert_eq!(Neighbourhood::Extended.offsets().len(), 24);
+
+        assert!("xx/oo".parse::<Neighbourhood>().is_err());
+        assert!("x../.x./...".parse::<Neighbourhood>().is_err());
+        assert!(".../.o./...".parse::<Neighbourhood>().is_err());
+        // Neighbours go both ways
+        assert_eq!(Neighbourhood::custom(vec![(-2, 2)]), Err("the neighbour at -2,2 needs the one at 2,-2 opposite".to_string()));
+        assert!("x......../........./........./........./....o..../........./........./........./.........".parse::<Neighbourhood>().is_err());
+    }
+}
@@ -0,0 +1,923 @@
+//! Cooperative play over TCP. A server owns the game of a shared infinite world, players connect to it
+//! as clients, send their actions and are kept up to date with the chunks they see. All integers are little endian.
+//!
+//! ```text
+//! frame:   length of the message: u32 | kind: u8 | fields
+//! cell:    kind: u8 | value: i8 | owner: u8
+//! ```
+//!
+//! Cells are 0 hidden with `value` their flag, 1 a revealed number or 2 a revealed mine of weight `value`.
+//! Their owner is the player who revealed them, `NOBODY` if no player did. Hidden cells never tell what they hold.
+//!
+//! Clients send:
+//!
+//! ```text
+//! 0 play:     action (see `Action::encode`)
+//! 1 cursor:   x: i32 | y: i32
+//! 2 view:     min x: i32 | min y: i32 | max x: i32 | max y: i32
+//! 3 restart:  nothing, ignored while the game is being played
+//! ```
+//!
+//! The server sends:
+//!
+//! ```text
+//! 0 welcome:  version: u16 | player: u8 | config (see `Config::encode`)
+//! 1 chunk:    chunk x: i32 | chunk y: i32 | CHUNK_LEN cells, row by row
+//! 2 cells:    count: u32 | x: i32 | y: i32 | cell, per cell
+//! 3 cursor:   player: u8 | x: i32 | y: i32
+//! 4 left:     player: u8
+//! 5 state:    state: u8 (0 playing, 1 lost, 2 won) | lives: u32
+//! ```
+//!
+//! A chunk is sent whole when it comes into the view of a player or a new world starts, then only its cells that change.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::*;
+use crate::codec::Reader;
+
+const VERSION: u16 = 1;
+/// Owner of the cells no player revealed, e.g. the mines shown once the game is lost.
+pub const NOBODY: u8 = u8::MAX;
+/// Messages longer than this are taken as garbage.
+const MAX_MESSAGE: usize = 1 << 20;
+/// Chunks a view spans at most in either direction around the cursor of its player,
+/// so that a player can't make the server generate the world.
+const MAX_VIEW: i32 = 32;
+/// Time between two ticks of the server, and between two reads while joining.
+pub const TICK: Duration = Duration::from_millis(10);
+/// Time the server has to welcome a player.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn invalid(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// A cell as players see it, with the player who revealed it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct SeenCell {
+    cell: Cell,
+    owner: u8,
+}
+
+impl SeenCell {
+    fn new(cell: &Cell, owner: Option<u8>) -> SeenCell {
+        if cell.revealed {
+            SeenCell { cell: *cell, owner: owner.unwrap_or(NOBODY) }
+        } else {
+            SeenCell { cell: Cell { flag: cell.flag, ..Cell::default() }, owner: NOBODY }
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        let (kind, value) = match (self.cell.revealed, self.cell.contents) {
+            (false, _) => (0, self.cell.flag),
+            (true, CellContents::Empty(nb)) => (1, nb),
+            (true, CellContents::Mine(weight)) => (2, weight),
+        };
+        out.extend_from_slice(&[kind, value as i8 as u8, self.owner]);
+    }
+
+    fn decode(reader: &mut Reader) -> Result<SeenCell, String> {
+        let (kind, value, owner) = (reader.u8()?, reader.u8()? as i8 as i32, reader.u8()?);
+        let cell = match kind {
+            0 => Cell { flag: value, ..Cell::default() },
+            1 => Cell { revealed: true, flag: 0, contents: CellContents::Empty(value) },
+            2 => Cell { revealed: true, flag: 0, contents: CellContents::Mine(value) },
+            _ => return Err(format!("unknown cell kind {}", kind)),
+        };
+        Ok(SeenCell { cell, owner })
+    }
+}
+
+fn encode_pos(pos: WorldPos, out: &mut Vec<u8>) {
+    out.extend_from_slice(&pos.x.to_le_bytes());
+    out.extend_from_slice(&pos.y.to_le_bytes());
+}
+
+fn decode_pos(reader: &mut Reader) -> Result<WorldPos, String> {
+    Ok(WorldPos::new(reader.i32()?, reader.i32()?))
+}
+
+/// What a client asks the server.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Request {
+    Play(Action),
+    /// The player points at this cell.
+    Cursor(WorldPos),
+    /// The player sees these chunks.
+    View(ChunkRect),
+    /// Starts a new world, once the game is over.
+    Restart,
+}
+
+impl Request {
+    fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        match *self {
+            Request::Play(action) => {
+                result.push(0);
+                action.encode(&mut result);
+            },
+            Request::Cursor(pos) => {
+                result.push(1);
+                encode_pos(pos, &mut result);
+            },
+            Request::View(view) => {
+                result.push(2);
+                for v in [view.min.x, view.min.y, view.max.x, view.max.y] {
+                    result.extend_from_slice(&v.to_le_bytes());
+                }
+            },
+            Request::Restart => result.push(3),
+        }
+        result
+    }
+
+    fn decode(data: &[u8]) -> Result<Request, String> {
+        let mut reader = Reader::new(data);
+        let result = match reader.u8()? {
+            0 => Request::Play(Action::decode(&mut reader)?),
+            1 => Request::Cursor(decode_pos(&mut reader)?),
+            2 => {
+                let min = ChunkPos::new(reader.i32()?, reader.i32()?);
+                let max = ChunkPos::new(reader.i32()?, reader.i32()?);
+                Request::View(ChunkRect::new(min, max))
+            },
+            3 => Request::Restart,
+            kind => return Err(format!("unknown request {}", kind)),
+        };
+        if !reader.is_empty() {
+            return Err("unexpected data after the request".to_string());
+        }
+        Ok(result)
+    }
+}
+
+/// What the server tells its clients.
+#[derive(Clone, Debug, PartialEq)]
+enum Update {
+    Welcome { player: u8, config: Config },
+    Chunk { position: ChunkPos, cells: Vec<SeenCell> },
+    Cells(Vec<(WorldPos, SeenCell)>),
+    Cursor { player: u8, pos: WorldPos },
+    Left(u8),
+    State { state: State, lives: u32 },
+}
+
+impl Update {
+    fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        match self {
+            Update::Welcome { player, config } => {
+                result.push(0);
+                result.extend_from_slice(&VERSION.to_le_bytes());
+                result.push(*player);
+                config.encode(&mut result);
+            },
+            Update::Chunk { position, cells } => {
+                result.push(1);
+                result.extend_from_slice(&position.x.to_le_bytes());
+                result.extend_from_slice(&position.y.to_le_bytes());
+                for c in cells {
+                    c.encode(&mut result);
+                }
+            },
+            Update::Cells(cells) => {
+                result.push(2);
+                result.extend_from_slice(&(cells.len() as u32).to_le_bytes());
+                for (pos, c) in cells {
+                    encode_pos(*pos, &mut result);
+                    c.encode(&mut result);
+                }
+            },
+            Update::Cursor { player, pos } => {
+                result.push(3);
+                result.push(*player);
+                encode_pos(*pos, &mut result);
+            },
+            Update::Left(player) => result.extend_from_slice(&[4, *player]),
+            Update::State { state, lives } => {
+                result.push(5);
+                result.push(match state {
+                    State::Playing => 0,
+                    State::Lost => 1,
+                    State::Won => 2,
+                });
+                result.extend_from_slice(&lives.to_le_bytes());
+            },
+        }
+        result
+    }
+
+    fn decode(data: &[u8]) -> Result<Update, String> {
+        let mut reader = Reader::new(data);
+        let result = match reader.u8()? {
+            0 => {
+                let version = reader.u16()?;
+                if version != VERSION {
+                    return Err(format!("the server speaks version {}, expected {}", version, VERSION));
+                }
+                Update::Welcome { player: reader.u8()?, config: Config::decode(&mut reader)? }
+            },
+            1 => {
+                let position = ChunkPos::new(reader.i32()?, reader.i32()?);
+                let cells = (0..CHUNK_LEN).map(|_| SeenCell::decode(&mut reader)).collect::<Result<_, _>>()?;
+                Update::Chunk { position, cells }
+            },
+            2 => {
+                let count = reader.u32()?;
+                let cells = (0..count).map(|_| Ok((decode_pos(&mut reader)?, SeenCell::decode(&mut reader)?))).collect::<Result<_, String>>()?;
+                Update::Cells(cells)
+            },
+            3 => Update::Cursor { player: reader.u8()?, pos: decode_pos(&mut reader)? },
+            4 => Update::Left(reader.u8()?),
+            5 => {
+                let state = match reader.u8()? {
+                    0 => State::Playing,
+                    1 => State::Lost,
+                    2 => State::Won,
+                    s => return Err(format!("unknown state {}", s)),
+                };
+                Update::State { state, lives: reader.u32()? }
+            },
+            kind => return Err(format!("unknown update {}", kind)),
+        };
+        if !reader.is_empty() {
+            return Err("unexpected data after the update".to_string());
+        }
+        Ok(result)
+    }
+}
+
+/// Frames messages over a non blocking stream, queueing what can't be written yet.
+struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    /// The other end closed the connection, what is left in `incoming` is all there is.
+    closed: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection { stream, incoming: Vec::new(), outgoing: Vec::new(), closed: false })
+    }
+
+    fn send(&mut self, message: &[u8]) {
+        self.outgoing.extend_from_slice(&(message.len() as u32).to_le_bytes());
+        self.outgoing.extend_from_slice(message);
+    }
+
+    /// Writes as much of what was sent as the stream takes.
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => { self.outgoing.drain(..n); },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// The next message received in full, if any. Fails once the other end is gone.
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buffer = [0; 4096];
+        while !self.closed {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.closed = true,
+                Ok(n) => self.incoming.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let len = self.incoming.get(..4).map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize);
+        if len.is_some_and(|len| len == 0 || len > MAX_MESSAGE) {
+            return Err(invalid(format!("message of {} bytes", len.unwrap())));
+        }
+        let Some(len) = len.filter(|len| self.incoming.len() >= 4 + len) else {
+            return match self.closed {
+                true => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")),
+                false => Ok(None),
+            };
+        };
+        let message = self.incoming[4..4 + len].to_vec();
+        self.incoming.drain(..4 + len);
+        Ok(Some(message))
+    }
+}
+
+struct Peer {
+    connection: Connection,
+    player: u8,
+    /// View as asked by the player.
+    asked: Option<ChunkRect>,
+    /// Part of `asked` kept up to date, see `MAX_VIEW`.
+    view: Option<ChunkRect>,
+    cursor: Option<WorldPos>,
+    /// Chunks of the view sent whole, only their cells that change are sent since.
+    sent: HashSet<ChunkPos>,
+}
+
+impl Peer {
+    /// Bounds the view asked around the cursor, or around the middle of the view until the player points somewhere.
+    fn bound_view(&mut self) {
+        self.view = self.asked.map(|asked| {
+            let center = self.cursor.map_or(
+                ChunkPos::new((asked.min.x + asked.max.x).div_euclid(2), (asked.min.y + asked.max.y).div_euclid(2)),
+                |pos| pos.chunk(),
+            );
+            let (min, max) = (center + (-MAX_VIEW / 2, -MAX_VIEW / 2), center + (MAX_VIEW / 2 - 1, MAX_VIEW / 2 - 1));
+            ChunkRect::new(
+                ChunkPos::new(asked.min.x.max(min.x), asked.min.y.max(min.y)),
+                ChunkPos::new(asked.max.x.min(max.x), asked.max.y.min(max.y)),
+            )
+        });
+    }
+}
+
+/// Plays a game of the infinite world for the players connected to it.
+/// Only the chunks players see are kept loaded, the others being saved as for a game played alone.
+pub struct Server {
+    listener: TcpListener,
+    game: Game,
+    save_dir: PathBuf,
+    peers: Vec<Peer>,
+    /// Player who revealed each cell of the loaded chunks, or whose flood fill reached it, for those revealed by one.
+    owners: HashMap<WorldPos, u8>,
+    /// State and lives as last sent.
+    sent_state: (State, u32),
+    /// Cells played since the last sync, sent to the players who see them.
+    changed: HashSet<WorldPos>,
+    /// Chunks to load, evict or send whole may have changed since the last sync:
+    /// a view moved, a player left, a chord reached out of every view or everything is to be sent again.
+    reload: bool,
+}
+
+impl Server {
+    /// Listens on `address` for players of a world of `seed`, saved in `<save_dir>/<seed>/`.
+    /// Finite boards aren't shared, the board is left out of `config`.
+    pub fn bind(address: impl ToSocketAddrs, seed: u64, mut config: Config, save_dir: &Path) -> io::Result<Server> {
+        config.board = None;
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let game = Game::new(Grid::persistent(seed, save_dir), config);
+        Ok(Server {
+            listener,
+            sent_state: (game.state(), game.lives()),
+            game,
+            save_dir: save_dir.to_path_buf(),
+            peers: Vec::new(),
+            owners: HashMap::new(),
+            changed: HashSet::new(),
+            reload: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Ticks forever.
+    pub fn run(&mut self) {
+        loop {
+            self.tick();
+            std::thread::sleep(TICK);
+        }
+    }
+
+    /// Welcomes new players, plays what was asked, carries on with flood fills, and tells everyone what changed.
+    pub fn tick(&mut self) {
+        self.accept();
+        let mut gone = Vec::new();
+        for i in 0..self.peers.len() {
+            loop {
+                match self.peers[i].connection.receive().and_then(|m| m.map(|m| Request::decode(&m).map_err(invalid)).transpose()) {
+                    Ok(Some(request)) => self.handle(i, request),
+                    Ok(None) => break,
+                    Err(e) => {
+                        println!("Player {} left: {}", self.peers[i].player, e);
+                        gone.push(self.peers[i].player);
+                        break;
+                    },
+                }
+            }
+        }
+        let revealed = self.game.update();
+        self.opened(revealed, None);
+        self.sync();
+        for peer in &mut self.peers {
+            if let Err(e) = peer.connection.flush() {
+                if !gone.contains(&peer.player) {
+                    println!("Player {} left: {}", peer.player, e);
+                    gone.push(peer.player);
+                }
+            }
+        }
+        if !gone.is_empty() {
+            self.peers.retain(|p| !gone.contains(&p.player));
+            self.reload = true;
+            for player in gone {
+                self.broadcast(&Update::Left(player));
+            }
+        }
+    }
+
+    fn broadcast(&mut self, update: &Update) {
+        let message = update.encode();
+        for peer in &mut self.peers {
+            peer.connection.send(&message);
+        }
+    }
+
+    fn accept(&mut self) {
+        loop {
+            let (stream, address) = match self.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    println!("Unable to accept a player: {}", e);
+                    return;
+                },
+            };
+            let Some(player) = (0..NOBODY).find(|p| self.peers.iter().all(|peer| peer.player != *p)) else {
+                println!("Turning {} away, the game is full", address);
+                continue;
+            };
+            let mut connection = match Connection::new(stream) {
+                Ok(connection) => connection,
+                Err(e) => {
+                    println!("Unable to set up the connection of {}: {}", address, e);
+                    continue;
+                },
+            };
+            println!("Player {} joined from {}", player, address);
+            connection.send(&Update::Welcome { player, config: self.game.config.clone() }.encode());
+            connection.send(&Update::State { state: self.game.state(), lives: self.game.lives() }.encode());
+            for other in &self.peers {
+                if let Some(pos) = other.cursor {
+                    connection.send(&Update::Cursor { player: other.player, pos }.encode());
+                }
+            }
+            self.peers.push(Peer { connection, player, asked: None, view: None, cursor: None, sent: HashSet::new() });
+        }
+    }
+
+    fn handle(&mut self, i: usize, request: Request) {
+        let player = self.peers[i].player;
+        match request {
+            Request::Play(action) => {
+                // Players only play where they see, the rest of the world being left unloaded
+                if let Action::Reveal(pos) | Action::Chord(pos) | Action::Flag(pos) = action {
+                    if !self.peers[i].view.is_some_and(|v| v.contains(pos.chunk())) {
+                        return;
+                    }
+                }
+                self.play(player, action);
+            },
+            Request::Cursor(pos) => {
+                let peer = &mut self.peers[i];
+                peer.cursor = Some(pos);
+                let view = peer.view;
+                peer.bound_view();
+                self.reload |= peer.view != view;
+                let message = Update::Cursor { player, pos }.encode();
+                for peer in self.peers.iter_mut().filter(|p| p.player != player) {
+                    peer.connection.send(&message);
+                }
+            },
+            Request::View(view) => {
+                self.peers[i].asked = Some(view);
+                self.peers[i].bound_view();
+                self.reload = true;
+            },
+            Request::Restart => self.restart(player),
+        }
+    }
+
+    /// Starts a new world for everyone once the game is lost, its seed drawn from the last one.
+    /// Players are then sent their view again in full.
+    fn restart(&mut self, player: u8) {
+        if self.game.state() == State::Playing {
+            return;
+        }
+        if let Err(e) = self.game.grid.save() {
+            println!("Unable to save world {}: {}", self.game.grid.seed(), e);
+        }
+        let seed = Rng::new(self.game.grid.seed()).next_u64();
+        self.game = Game::new(Grid::persistent(seed, &self.save_dir), self.game.config.clone());
+        self.owners.clear();
+        self.resend();
+        println!("Player {} started a new world", player);
+    }
+
+    /// Sends every player their whole view again at the next sync.
+    fn resend(&mut self) {
+        for peer in &mut self.peers {
+            peer.sent.clear();
+        }
+        self.reload = true;
+    }
+
+    /// Plays the action of `player`, whose flood fills carry on over the next ticks.
+    fn play(&mut self, player: u8, action: Action) {
+        let revealed = match action {
+            Action::Reveal(pos) => self.game.reveal(pos),
+            Action::Chord(pos) => self.game.chord(pos),
+            Action::Flag(pos) => {
+                self.game.flag(pos);
+                self.changed.insert(pos);
+                Revealed::default()
+            },
+            Action::AutoFlag => {
+                self.changed.extend(self.game.auto_flag());
+                Revealed::default()
+            },
+            // Taking back moves or showing the mines would spoil the game of the others
+            Action::ShowMines | Action::Undo | Action::Redo | Action::Camera { .. } => return,
+        };
+        // A chord next to the edge of a view may have loaded chunks no player sees
+        self.reload = true;
+        self.opened(revealed, Some(player));
+        if self.game.state() != State::Playing {
+            // Mines are shown all over the loaded chunks
+            self.resend();
+        }
+    }
+
+    /// Keeps track of the cells opened by `player`, or else by the flood fill of the opening next to them.
+    fn opened(&mut self, revealed: Revealed, player: Option<u8>) {
+        for pos in revealed.cells {
+            let grid = &self.game.grid;
+            let owner = player.or_else(|| grid.topology().neighbours(pos).into_iter()
+                .filter(|p| grid.get_cell(*p).is_some_and(|c| c.revealed && c.contents == CellContents::Empty(0)))
+                .find_map(|p| self.owners.get(&p).copied()));
+            if let Some(owner) = owner {
+                self.owners.entry(pos).or_insert(owner);
+            }
+            self.changed.insert(pos);
+        }
+    }
+
+    /// Sends each player the cells that changed in the chunks they were sent, then the chunks that came into their view.
+    fn sync(&mut self) {
+        let state = (self.game.state(), self.game.lives());
+        if state != self.sent_state {
+            self.sent_state = state;
+            self.broadcast(&Update::State { state: state.0, lives: state.1 });
+        }
+        let reload = std::mem::take(&mut self.reload);
+        if reload {
+            let views: Vec<ChunkRect> = self.peers.iter().filter_map(|p| p.view).collect();
+            if let Err(e) = self.game.grid.update_views(&views) {
+                println!("Unable to save world {}: {}", self.game.grid.seed(), e);
+            }
+            let grid = &self.game.grid;
+            self.owners.retain(|pos, _| grid.get_cell(*pos).is_some());
+        }
+        let changed = std::mem::take(&mut self.changed);
+        for peer in &mut self.peers {
+            let Some(view) = peer.view else {
+                continue;
+            };
+            let cells: Vec<(WorldPos, SeenCell)> = changed.iter()
+                .filter(|pos| peer.sent.contains(&pos.chunk()))
+                .filter_map(|pos| Some((*pos, SeenCell::new(self.game.grid.get_cell(*pos)?, self.owners.get(pos).copied()))))
+                .collect();
+            if !cells.is_empty() {
+                peer.connection.send(&Update::Cells(cells).encode());
+            }
+            if !reload {
+                continue;
+            }
+            peer.sent.retain(|pos| view.contains(*pos));
+            for position in view.iter() {
+                if !peer.sent.insert(position) {
+                    continue;
+                }
+                let cells = self.game.grid.chunk_or_create(position).cells()
+                    .map(|(local, cell)| SeenCell::new(cell, self.owners.get(&position.world(local)).copied()))
+                    .collect();
+                peer.connection.send(&Update::Chunk { position, cells }.encode());
+            }
+        }
+    }
+}
+
+/// A player of a game played on a server. The game the client is given mirrors what the player sees of it.
+pub struct Client {
+    connection: Connection,
+    player: u8,
+    /// Cell each other player points at.
+    cursors: HashMap<u8, WorldPos>,
+    /// Player who revealed each cell received, for those revealed by one.
+    owners: HashMap<WorldPos, u8>,
+    /// Cursor and view as last sent.
+    cursor: Option<WorldPos>,
+    view: Option<ChunkRect>,
+}
+
+impl Client {
+    /// Joins the game of the server at `address`. The game returned holds no chunk yet,
+    /// they come with `poll` once `look_at` tells which ones the player sees.
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<(Client, Game)> {
+        let mut connection = Connection::new(TcpStream::connect(address)?)?;
+        let start = Instant::now();
+        let (player, config) = loop {
+            if let Some(message) = connection.receive()? {
+                match Update::decode(&message).map_err(invalid)? {
+                    Update::Welcome { player, config } => break (player, config),
+                    _ => return Err(invalid("expected a welcome".to_string())),
+                }
+            }
+            if start.elapsed() > JOIN_TIMEOUT {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "no welcome from the server"));
+            }
+            std::thread::sleep(TICK);
+        };
+        println!("Joined as player {}", player);
+        let game = Game::new(Grid::remote(), config);
+        let client = Client { connection, player, cursors: HashMap::new(), owners: HashMap::new(), cursor: None, view: None };
+        Ok((client, game))
+    }
+
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    /// Cell each other player points at.
+    pub fn cursors(&self) -> &HashMap<u8, WorldPos> {
+        &self.cursors
+    }
+
+    /// Player who revealed the cell at `pos`, `None` if it is hidden or no player revealed it.
+    pub fn owner(&self, pos: WorldPos) -> Option<u8> {
+        self.owners.get(&pos).copied()
+    }
+
+    /// Asks the server to play `action`. Only reveals, chords and flags, automatic ones included, are played.
+    pub fn play(&mut self, action: Action) {
+        self.connection.send(&Request::Play(action).encode());
+    }
+
+    /// Asks the server for a new world, which it only starts once the game is over.
+    pub fn restart(&mut self) {
+        self.connection.send(&Request::Restart.encode());
+    }
+
+    /// Shows the others the player points at `pos`.
+    pub fn point_at(&mut self, pos: WorldPos) {
+        if self.cursor != Some(pos) {
+            self.cursor = Some(pos);
+            self.connection.send(&Request::Cursor(pos).encode());
+        }
+    }
+
+    /// Asks to be kept up to date with the chunks of `view`.
+    pub fn look_at(&mut self, view: ChunkRect) {
+        if self.view != Some(view) {
+            self.view = Some(view);
+            self.connection.send(&Request::View(view).encode());
+        }
+    }
+
+    /// Sends what was asked, then applies to `game` what the server sent, returning whether it sent anything.
+    /// Fails once the server is gone.
+    pub fn poll(&mut self, game: &mut Game) -> io::Result<bool> {
+        self.connection.flush()?;
+        let mut received = false;
+        while let Some(message) = self.connection.receive()? {
+            received = true;
+            match Update::decode(&message).map_err(invalid)? {
+                Update::Welcome { .. } => return Err(invalid("welcomed twice".to_string())),
+                Update::Chunk { position, cells } => {
+                    let mut elems = [Cell::default(); CHUNK_LEN];
+                    for (i, seen) in cells.into_iter().enumerate() {
+                        elems[i] = seen.cell;
+                        self.set_owner(position.world(LocalPos::from_idx(i)), seen.owner);
+                    }
+                    game.grid.insert_chunk(Chunk::from_cells(position, elems));
+                },
+                Update::Cells(cells) => {
+                    for (pos, seen) in cells {
+                        if let Some(cell) = game.grid.get_cell_mut(pos) {
+                            *cell = seen.cell;
+                        }
+                        self.set_owner(pos, seen.owner);
+                    }
+                },
+                Update::Cursor { player, pos } => {
+                    self.cursors.insert(player, pos);
+                },
+                Update::Left(player) => {
+                    println!("Player {} left", player);
+                    self.cursors.remove(&player);
+                },
+                Update::State { state, lives } => game.set_state(state, lives),
+            }
+        }
+        Ok(received)
+    }
+
+    fn set_owner(&mut self, pos: WorldPos, owner: u8) {
+        if owner == NOBODY {
+            self.owners.remove(&pos);
+        } else {
+            self.owners.insert(pos, owner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use crate::*;
+
+    /// An empty directory of its own for the world of a test.
+    fn save_dir(test: &str) -> PathBuf {
+        let result = std::env::temp_dir().join(format!("minesweeper-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&result);
+        result
+    }
+
+    /// Polls `client` until `done` holds.
+    fn poll_until(client: &mut Client, game: &mut Game, done: impl Fn(&Client, &Game) -> bool) {
+        let start = Instant::now();
+        while !done(client, game) {
+            assert!(start.elapsed() < Duration::from_secs(5), "nothing came from the server");
+            client.poll(game).unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn players_share_the_world() {
+        let (seed, config) = (11, Config::default());
+        let dir = save_dir("share");
+        let mut server = Server::bind("127.0.0.1:0", seed, config.clone(), &dir).unwrap();
+        let address = server.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let running = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    server.tick();
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                server
+            })
+        };
+
+        let (mut a, mut game_a) = Client::connect(address).unwrap();
+        let (mut b, mut game_b) = Client::connect(address).unwrap();
+        assert_ne!(a.player(), b.player());
+        assert_eq!(game_b.config, config);
+        let view = ChunkRect::new(ChunkPos::new(-1, -1), ChunkPos::new(1, 1));
+        b.look_at(view);
+        poll_until(&mut b, &mut game_b, |_, g| view.iter().all(|c| g.grid.get_cell(c.origin()).is_some()));
+
+        // Cells out of sight can't be played
+        let far = WorldPos::new(10 * CHUNK_SIZE as i32, 0);
+        a.play(Action::Reveal(far));
+        a.look_at(view);
+
+        // What one player opens shows for the other, as theirs, and hidden cells keep their mines to the server
+        let pos = WorldPos::new(3, 5);
+        a.play(Action::Reveal(pos));
+        a.poll(&mut game_a).unwrap();
+        let mut local = Game::new(Grid::new(seed), config);
+        local.grid.update_chunks(view).unwrap();
+        local.reveal(pos);
+        while local.grid.is_revealing() {
+            local.update();
+        }
+        // The flood fill carries on over several ticks
+        let opened = local.grid.revealed_count() as usize;
+        let cells: Vec<WorldPos> = view.iter().flat_map(|c| (0..CHUNK_LEN).map(move |i| c.world(LocalPos::from_idx(i)))).collect();
+        poll_until(&mut b, &mut game_b, |_, g| cells.iter().filter(|p| g.grid.get_cell(**p).unwrap().revealed).count() == opened);
+        for chunk in view.iter() {
+            for (local_pos, seen) in game_b.grid.chunk_or_create(chunk).cells() {
+                let p = chunk.world(local_pos);
+                let expected = local.grid.get_cell_or_create(p).unwrap();
+                assert_eq!(seen.revealed, expected.revealed);
+                if seen.revealed {
+                    assert_eq!(seen.contents, expected.contents);
+                    assert_eq!(b.owner(p), Some(a.player()));
+                } else {
+                    assert!(!seen.is_mine());
+                }
+            }
+        }
+
+        let hidden = *cells.iter().find(|p| !game_b.grid.get_cell(**p).unwrap().revealed).unwrap();
+        a.play(Action::Flag(hidden));
+        a.poll(&mut game_a).unwrap();
+        poll_until(&mut b, &mut game_b, |_, g| g.grid.get_cell(hidden).unwrap().is_flagged());
+
+        // Cursors show for the others until they leave
+        let player_b = b.player();
+        b.point_at(hidden);
+        b.poll(&mut game_b).unwrap();
+        poll_until(&mut a, &mut game_a, |c, _| c.cursors().get(&player_b) == Some(&hidden));
+        drop(b);
+        poll_until(&mut a, &mut game_a, |c, _| !c.cursors().contains_key(&player_b));
+
+        stop.store(true, Ordering::Relaxed);
+        let mut server = running.join().unwrap();
+        assert!(server.game.grid.get_cell_or_create(far).is_some_and(|c| !c.revealed));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn server_keeps_what_players_see() {
+        let dir = save_dir("views");
+        let server = Arc::new(Mutex::new(Server::bind("127.0.0.1:0", 3, Config::default(), &dir).unwrap()));
+        let address = server.lock().unwrap().local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let running = {
+            let (server, stop) = (server.clone(), stop.clone());
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    server.lock().unwrap().tick();
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+
+        // A view larger than allowed is cut down around the cursor
+        let (mut client, mut game) = Client::connect(address).unwrap();
+        client.look_at(ChunkRect::new(ChunkPos::new(-100, -100), ChunkPos::new(100, 100)));
+        client.point_at(WorldPos::new(0, 0));
+        poll_until(&mut client, &mut game, |_, g| g.grid.get_cell(WorldPos::new(0, 0)).is_some());
+        let view = server.lock().unwrap().peers[0].view.unwrap();
+        assert_eq!((view.min, view.max), (ChunkPos::new(-16, -16), ChunkPos::new(15, 15)));
+
+        // Chunks no one sees any more are dropped, what was played in them is saved
+        client.play(Action::Reveal(WorldPos::new(3, 3)));
+        let far = WorldPos::new(50 * CHUNK_SIZE as i32, 0);
+        client.look_at(ChunkRect::new(far.chunk(), far.chunk()));
+        client.point_at(far);
+        poll_until(&mut client, &mut game, |_, g| g.grid.get_cell(far).is_some());
+        {
+            let mut server = server.lock().unwrap();
+            assert!(server.game.grid.get_cell(WorldPos::new(3, 3)).is_none());
+            assert!(server.game.grid.get_cell_or_create(WorldPos::new(3, 3)).unwrap().revealed);
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        running.join().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lost_world_restarts() {
+        let dir = save_dir("restart");
+        let mut server = Server::bind("127.0.0.1:0", 5, Config::default(), &dir).unwrap();
+        let address = server.local_addr().unwrap();
+        server.game.reveal(WorldPos::new(8, 8));
+        let mine = (0..CHUNK_LEN as i32).map(|i| WorldPos::new(i % 16, i / 16))
+            .find(|p| server.game.grid.get_cell(*p).unwrap().is_mine())
+            .unwrap();
+        server.game.reveal(mine);
+        let stop = Arc::new(AtomicBool::new(false));
+        let running = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    server.tick();
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+
+        // Chunks not received yet are hidden and empty, nothing is generated on the client
+        let (mut client, mut game) = Client::connect(address).unwrap();
+        let view = ChunkRect::new(ChunkPos::new(0, 0), ChunkPos::new(0, 0));
+        game.grid.update_chunks(view).unwrap();
+        assert!(game.grid.chunk_or_create(ChunkPos::new(0, 0)).cells().all(|(_, c)| *c == Cell::default()));
+        client.look_at(view);
+        poll_until(&mut client, &mut game, |_, g| g.state() == State::Lost && g.grid.get_cell(mine).unwrap().revealed);
+
+        client.restart();
+        poll_until(&mut client, &mut game, |_, g| g.state() == State::Playing && !g.grid.get_cell(mine).unwrap().revealed);
+        assert!(game.grid.chunk_or_create(ChunkPos::new(0, 0)).cells().all(|(_, c)| !c.revealed));
+
+        stop.store(true, Ordering::Relaxed);
+        running.join().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
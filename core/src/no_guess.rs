@@ -0,0 +1,275 @@
+//! Layouts that can be solved without guessing. A simulated player opens the safe zone, or comes in
+//! from the chunks already generated around, and plays only what the solver can deduce.
+//! Layouts are rolled again until the player gets through, or the time budget runs out.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::*;
+
+/// Seed of the `attempt`th layout. The first attempt is the plain layout.
+fn reroll_seed(seed: u64, attempt: u64) -> u64 {
+    if attempt == 0 { seed } else { Rng::new(seed ^ attempt).next_u64() }
+}
+
+/// A simulated player, opening `cells` only when sure they are safe.
+struct Sandbox<'a> {
+    topology: &'a dyn Topology,
+    weights: MineWeights,
+    /// Mines held by a cell, 0 for a safe one.
+    mines: &'a dyn Fn(WorldPos) -> i32,
+    /// What the player sees of cells outside of `cells`.
+    around: &'a dyn Fn(WorldPos) -> Seen,
+    cells: HashSet<WorldPos>,
+    revealed: HashSet<WorldPos>,
+    flags: HashMap<WorldPos, i32>,
+}
+
+impl Sandbox<'_> {
+    fn view(&self, pos: WorldPos) -> Seen {
+        if !self.cells.contains(&pos) {
+            (self.around)(pos)
+        } else if self.revealed.contains(&pos) {
+            Seen::Number(self.topology.neighbours(pos).into_iter().map(self.mines).sum())
+        } else if let Some(weight) = self.flags.get(&pos) {
+            Seen::Flagged(*weight)
+        } else {
+            Seen::Hidden
+        }
+    }
+
+    fn reveal(&mut self, pos: WorldPos) {
+        let mut pending = vec![pos];
+        while let Some(pos) = pending.pop() {
+            if !self.cells.contains(&pos) || (self.mines)(pos) != 0 || !self.revealed.insert(pos) {
+                continue;
+            }
+            let neighbours = self.topology.neighbours(pos);
+            if neighbours.iter().all(|p| (self.mines)(*p) == 0) {
+                pending.extend(neighbours);
+            }
+        }
+    }
+
+    /// Plays until stuck, `numbers` being the revealed cells around `cells`.
+    /// Returns how many safe cells are left hidden among `interesting`.
+    fn play(&mut self, numbers: &[WorldPos], interesting: impl Fn(WorldPos) -> bool) -> usize {
+        loop {
+            let cells = self.revealed.iter().chain(numbers).copied().collect::<Vec<_>>();
+            let result = Solver::from_view(cells, self.topology, self.weights, |pos| self.view(pos)).solve();
+            let before = (self.revealed.len(), self.flags.len());
+            for (pos, weight) in result.weights {
+                if self.cells.contains(&pos) {
+                    self.flags.insert(pos, weight);
+                }
+            }
+            for pos in result.safe {
+                self.reveal(pos);
+            }
+            if before == (self.revealed.len(), self.flags.len()) {
+                break;
+            }
+        }
+        self.cells.iter()
+            .filter(|p| interesting(**p) && (self.mines)(**p) == 0 && !self.revealed.contains(p))
+            .count()
+    }
+}
+
+/// Mines of a finite board that can be solved from the first click at `safe`.
+/// Out of time, the layout leaving the fewest cells to guess is kept.
+/// Returns the layout and how many safe cells the player can't reach without guessing.
+pub(crate) fn board_layout(
+    size: BoardSize,
+    seed: u64,
+    topology: &dyn Topology,
+    weights: MineWeights,
+    safe: WorldPos,
+    budget: Duration,
+) -> (Vec<bool>, usize) {
+    let start = Instant::now();
+    let mut best: Option<(usize, Vec<bool>)> = None;
+    for attempt in 0.. {
+        let layout = size.mine_layout(reroll_seed(seed, attempt), &topology.area(safe));
+        let is_mine = |pos: WorldPos| size.contains(pos) && layout[(pos.x as u32 + pos.y as u32 * size.width) as usize];
+        // Weights don't change with the layout, they come from the real seed
+        let mines = |pos: WorldPos| if is_mine(pos) { weights.weight(seed, pos) } else { 0 };
+        let mut sandbox = Sandbox {
+            topology,
+            weights,
+            mines: &mines,
+            around: &|_| Seen::Outside,
+            cells: (0..size.width as i32 * size.height as i32)
+                .map(|i| WorldPos::new(i % size.width as i32, i / size.width as i32))
+                .collect(),
+            revealed: HashSet::new(),
+            flags: HashMap::new(),
+        };
+        sandbox.reveal(safe);
+        let left = sandbox.play(&[], |_| true);
+        if best.as_ref().is_none_or(|(l, _)| left < *l) {
+            best = Some((left, layout));
+        }
+        if left == 0 || start.elapsed() > budget {
+            break;
+        }
+    }
+    let (left, layout) = best.unwrap();
+    (layout, left)
+}
+
+/// What is known of the chunks around the one being generated.
+pub(crate) struct Surroundings {
+    /// Chunks already generated, whose cells the simulated player is assumed to know.
+    pub known: HashMap<ChunkPos, [Cell; CHUNK_LEN]>,
+    /// Mines of the chunks around that aren't generated yet, as the plain generator would place them.
+    pub unknown: HashMap<ChunkPos, [bool; CHUNK_LEN]>,
+}
+
+/// Mines of a chunk of the infinite world that the player can clear coming from the chunks
+/// already generated around it, and from the safe zone if it is in that chunk.
+/// Cells of the chunks not generated yet close to the border can be opened too.
+/// Out of time, the layout leaving the fewest cells to guess is kept.
+/// Returns the layout and whether it differs from the plain one.
+/// Seed, topology, weights and safe zone are those of `grid`.
+pub(crate) fn chunk_layout(
+    position: ChunkPos,
+    mines: u32,
+    grid: &Grid,
+    around: &Surroundings,
+    budget: Duration,
+) -> ([bool; CHUNK_LEN], bool) {
+    // How far into the chunks not generated yet the player may go
+    const MARGIN: i32 = 2;
+
+    let (seed, topology, weights, safe_zone) = (grid.seed(), grid.topology(), grid.weights(), grid.safe_zone());
+    let safe = safe_zone.map_or(Vec::new(), |p| topology.area(p));
+    let plain = Chunk::mine_layout(position, mines, seed, &safe);
+    let start_at = safe_zone.filter(|p| p.chunk() == position);
+    let origin = position.origin();
+    let in_chunk = |pos: WorldPos| pos.chunk() == position;
+    // Cells of the other chunks whose numbers count mines of this one
+    let mut border: Vec<WorldPos> = (0..CHUNK_LEN)
+        .flat_map(|i| topology.neighbours(position.world(LocalPos::from_idx(i))))
+        .filter(|p| !in_chunk(*p))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    border.sort_by_key(|p| (p.y, p.x));
+    // Revealed numbers of the known chunks along the border
+    let mut numbers = Vec::new();
+    for pos in border {
+        let Some(cells) = around.known.get(&pos.chunk()) else {
+            continue;
+        };
+        let cell = cells[pos.local().idx()];
+        if cell.revealed {
+            // The player already saw numbers counting mines of this chunk
+            return (plain, false);
+        }
+        if !cell.is_mine() {
+            numbers.push(pos);
+        }
+    }
+    if numbers.is_empty() && start_at.is_none() {
+        return (plain, false);
+    }
+
+    let cells: HashSet<WorldPos> = (-MARGIN..CHUNK_SIZE as i32 + MARGIN)
+        .flat_map(|y| (-MARGIN..CHUNK_SIZE as i32 + MARGIN).map(move |x| origin + (x, y)))
+        .filter(|p| in_chunk(*p) || around.unknown.contains_key(&p.chunk()))
+        .collect();
+    let start = Instant::now();
+    let mut best: Option<(usize, [bool; CHUNK_LEN])> = None;
+    for attempt in 0.. {
+        let layout = if attempt == 0 {
+            plain
+        } else {
+            Chunk::mine_layout(position, mines, reroll_seed(seed, attempt), &safe)
+        };
+        let is_mine = |pos: WorldPos| {
+            if in_chunk(pos) {
+                return layout[pos.local().idx()];
+            }
+            match (around.known.get(&pos.chunk()), around.unknown.get(&pos.chunk())) {
+                (Some(cells), _) => cells[pos.local().idx()].is_mine(),
+                (_, Some(mines)) => mines[pos.local().idx()],
+                _ => false,
+            }
+        };
+        let mines = |pos: WorldPos| if is_mine(pos) { weights.weight(seed, pos) } else { 0 };
+        let known = |pos: WorldPos| match around.known.get(&pos.chunk()) {
+            Some(cells) if cells[pos.local().idx()].is_mine() => Seen::Flagged(mines(pos)),
+            Some(_) => Seen::Number(topology.neighbours(pos).into_iter().map(mines).sum()),
+            None => Seen::Hidden,
+        };
+        let mut sandbox = Sandbox {
+            topology,
+            weights,
+            mines: &mines,
+            around: &known,
+            cells: cells.clone(),
+            revealed: HashSet::new(),
+            flags: HashMap::new(),
+        };
+        if let Some(pos) = start_at {
+            sandbox.reveal(pos);
+        }
+        let left = sandbox.play(&numbers, in_chunk);
+        if best.as_ref().is_none_or(|(l, _)| left < *l) {
+            best = Some((left, layout));
+        }
+        if left == 0 || start.elapsed() > budget {
+            break;
+        }
+    }
+    let layout = best.unwrap().1;
+    (layout, layout != plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::*;
+
+    #[test]
+    fn no_guess_board_is_solved_by_the_solver() {
+        let size = BoardSize::INTERMEDIATE;
+        let config = Config { no_guess: true, no_guess_budget: Duration::from_secs(5), ..Config::default() };
+        let mut game = Game::new(Grid::fixed(7, size), config);
+        game.reveal(WorldPos::new(8, 8));
+        while game.state() == State::Playing {
+            let result = Solver::new(&game.grid).solve();
+            assert!(!result.safe.is_empty(), "the solver is stuck");
+            for pos in result.safe {
+                game.reveal(pos);
+            }
+            for pos in result.mines {
+                if !game.grid.get_cell(pos).unwrap().is_flagged() {
+                    game.flag(pos);
+                }
+            }
+        }
+        assert_eq!(game.state(), State::Won);
+    }
+
+    #[test]
+    fn rolled_again_chunks_are_counted() {
+        let mut grid = Grid::new(2);
+        grid.set_no_guess(Some(Duration::from_millis(20)));
+        grid.set_safe_zone(WorldPos::new(3, 3));
+        grid.update_chunks(ChunkRect::new(ChunkPos::new(-1, -1), ChunkPos::new(1, 1))).unwrap();
+        for y in 0..CHUNK_SIZE as i32 {
+            for x in 0..CHUNK_SIZE as i32 {
+                let pos = WorldPos::new(x, y);
+                let expected = NEIGHBOURS.iter().filter(|o| grid.get_cell(pos + *o).unwrap().is_mine()).count() as i32;
+                match grid.get_cell(pos).unwrap().contents {
+                    CellContents::Empty(nb) => assert_eq!(nb, expected, "cell {:?}", pos),
+                    CellContents::Mine(_) => {},
+                }
+            }
+        }
+        assert!(!grid.get_cell(WorldPos::new(3, 3)).unwrap().is_mine());
+    }
+}
@@ -0,0 +1,224 @@
+//! Recordings of games, to watch them again or share them. All integers are little endian.
+//!
+//! ```text
+//! header:  magic "MSRP" | version: u16 | seed: u64 | config (see `Config::encode`) | action count: u32
+//! action:  time in ms since the start: u32 | kind: u8 | a: i32 | b: i32 | c: i32
+//! ```
+//!
+//! Kinds are 0 reveal, 1 flag, 2 chord at (a, b), 3 show all mines, 4 camera moved to (a, b) with zoom c,
+//! 5 undo, 6 redo and 7 flag what the solver finds.
+//! Playing a replay again needs the same generation, so it starts from a world kept in memory only.
+//! With `no_guess`, layouts depend on how much was found within the time budget
+//! and a replay can go out of sync on a slower machine.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::*;
+use crate::codec::Reader;
+
+const MAGIC: &[u8; 4] = b"MSRP";
+const VERSION: u16 = 1;
+
+/// Something the player did.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Reveal(WorldPos),
+    Flag(WorldPos),
+    Chord(WorldPos),
+    ShowMines,
+    Undo,
+    Redo,
+    /// Flags every mine the solver finds.
+    AutoFlag,
+    /// The view moved, to `x` and `y` in pixels at `zoom` pixels per cell. Doesn't change the game.
+    Camera { x: i32, y: i32, zoom: i32 },
+}
+
+impl Action {
+    /// Plays the action in `game`, returning the cells it opened.
+    pub fn apply(&self, game: &mut Game) -> Revealed {
+        match *self {
+            Action::Reveal(pos) => return game.reveal(pos),
+            Action::Chord(pos) => return game.chord(pos),
+            Action::Flag(pos) => game.flag(pos),
+            Action::ShowMines => game.show_all_mines(),
+            Action::Undo => { game.undo(); },
+            Action::Redo => { game.redo(); },
+            Action::AutoFlag => { game.auto_flag(); },
+            Action::Camera { .. } => {},
+        }
+        Revealed::default()
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        let (kind, a, b, c) = match *self {
+            Action::Reveal(pos) => (0, pos.x, pos.y, 0),
+            Action::Flag(pos) => (1, pos.x, pos.y, 0),
+            Action::Chord(pos) => (2, pos.x, pos.y, 0),
+            Action::ShowMines => (3, 0, 0, 0),
+            Action::Camera { x, y, zoom } => (4, x, y, zoom),
+            Action::Undo => (5, 0, 0, 0),
+            Action::Redo => (6, 0, 0, 0),
+            Action::AutoFlag => (7, 0, 0, 0),
+        };
+        out.push(kind);
+        for v in [a, b, c] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+
+    pub(crate) fn decode(reader: &mut Reader) -> Result<Action, String> {
+        let kind = reader.u8()?;
+        let (a, b, c) = (reader.i32()?, reader.i32()?, reader.i32()?);
+        Ok(match kind {
+            0 => Action::Reveal(WorldPos::new(a, b)),
+            1 => Action::Flag(WorldPos::new(a, b)),
+            2 => Action::Chord(WorldPos::new(a, b)),
+            3 => Action::ShowMines,
+            4 => Action::Camera { x: a, y: b, zoom: c },
+            5 => Action::Undo,
+            6 => Action::Redo,
+            7 => Action::AutoFlag,
+            _ => return Err(format!("unknown action {}", kind)),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimedAction {
+    /// Milliseconds since the start of the game.
+    pub time: u32,
+    pub action: Action,
+}
+
+/// A game from the start: how it was set up and everything the player did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: Config,
+    pub actions: Vec<TimedAction>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, config: Config) -> Replay {
+        Replay { seed, config, actions: Vec::new() }
+    }
+
+    pub fn push(&mut self, time: u32, action: Action) {
+        self.actions.push(TimedAction { time, action });
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(MAGIC);
+        result.extend_from_slice(&VERSION.to_le_bytes());
+        result.extend_from_slice(&self.seed.to_le_bytes());
+        self.config.encode(&mut result);
+        result.extend_from_slice(&(self.actions.len() as u32).to_le_bytes());
+        for a in &self.actions {
+            result.extend_from_slice(&a.time.to_le_bytes());
+            a.action.encode(&mut result);
+        }
+        result
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Replay, String> {
+        let mut reader = Reader::new(data);
+        reader.header(MAGIC, "replay", VERSION)?;
+        let seed = reader.u64()?;
+        let config = Config::decode(&mut reader)?;
+        let count = reader.u32()?;
+        let mut actions = Vec::new();
+        for _ in 0..count {
+            let time = reader.u32()?;
+            actions.push(TimedAction { time, action: Action::decode(&mut reader)? });
+        }
+        if !reader.is_empty() {
+            return Err("unexpected data after the last action".to_string());
+        }
+        Ok(Replay { seed, config, actions })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.encode())
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let data = fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        Replay::decode(&data).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The game as it was before the first action, kept in memory only.
+    pub fn start(&self) -> Game {
+        let grid = match self.config.board {
+            Some(size) => Grid::fixed(self.seed, size),
+            None => Grid::new(self.seed),
+        };
+        Game::new(grid, self.config.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn round_trip() {
+        let density = DensityCurve { start: 0.05, end: 0.2, ramp: 500, variance: 0.01 };
+        let mut config = Config { board: Some(BoardSize::EXPERT), density, topology: TopologyKind::Torus, ..Config::default() };
+        config.neighbourhood = "x.x../...x./..o../.x.../..x.x".parse().unwrap();
+        config.weights = "0.2/0.1/0.1".parse().unwrap();
+        let mut replay = Replay::new(42, config);
+        replay.push(0, Action::Camera { x: -12, y: 30, zoom: 16 });
+        replay.push(150, Action::Reveal(WorldPos::new(-3, 7)));
+        replay.push(900, Action::Flag(WorldPos::new(1, 2)));
+        replay.push(1000, Action::Chord(WorldPos::new(1, 3)));
+        replay.push(1100, Action::Undo);
+        replay.push(1150, Action::Redo);
+        replay.push(1160, Action::AutoFlag);
+        replay.push(1200, Action::ShowMines);
+        assert_eq!(Replay::decode(&replay.encode()), Ok(replay.clone()));
+
+        let data = replay.encode();
+        assert!(Replay::decode(&data[..data.len() - 1]).is_err());
+        assert!(Replay::decode(b"MSRG").is_err());
+    }
+
+    #[test]
+    fn playing_again_gives_the_same_game() {
+        let mut replay = Replay::new(9, Config { board: Some(BoardSize::INTERMEDIATE), ..Config::default() });
+        let mut game = replay.start();
+        let mut play = |game: &mut Game, action| {
+            replay.push(0, action);
+            action.apply(game);
+        };
+        play(&mut game, Action::Reveal(WorldPos::new(5, 5)));
+        for _ in 0..3 {
+            let deductions = Solver::new(&game.grid).solve();
+            for pos in deductions.mines {
+                play(&mut game, Action::Flag(pos));
+            }
+            for pos in deductions.safe {
+                play(&mut game, Action::Reveal(pos));
+            }
+        }
+
+        let mut again = replay.start();
+        for a in &replay.actions {
+            a.action.apply(&mut again);
+        }
+        assert_eq!(again.state(), game.state());
+        assert_eq!(again.grid.revealed_count(), game.grid.revealed_count());
+        for y in 0..16 {
+            for x in 0..16 {
+                let pos = WorldPos::new(x, y);
+                assert_eq!(again.grid.get_cell(pos), game.grid.get_cell(pos));
+            }
+        }
+    }
+}
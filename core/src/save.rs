@@ -0,0 +1,259 @@
+//! A game saved to be carried on later. All integers are little endian.
+//!
+//! ```text
+//! header:    magic "MSSV" | version: u16 | seed: u64 | config (see `Config::encode`)
+//! game:      state: u8 | elapsed time in ms: u64 | safe cells revealed: u64
+//! camera:    x: i32 | y: i32 | zoom: i32
+//! safe zone: present: u8 | x: i32 | y: i32
+//! progress:  clicks: u32 | distance travelled in cells: f32
+//! lives:     lives left: u32
+//! cells:     count: u32 | one byte per cell, as in region files
+//! fill:      count: u32 | x: i32 | y: i32 per cell
+//! ```
+//!
+//! States are 0 playing, 1 lost and 2 won.
+//! Cells of a finite board are all in the save, row by row. The infinite world keeps its chunks
+//! in the region files of its save directory instead, so the save holds no cell for it.
+//! The fill lists the cells a flood fill in progress is still to visit, so that it carries on.
+//! The undo history isn't saved.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::*;
+use crate::codec::Reader;
+
+const MAGIC: &[u8; 4] = b"MSSV";
+const VERSION: u16 = 1;
+
+/// Everything needed to carry on with a game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedGame {
+    pub seed: u64,
+    pub config: Config,
+    pub state: State,
+    /// Milliseconds spent playing.
+    pub elapsed: u64,
+    /// Where the view was, `x` and `y` in pixels at `zoom` pixels per cell.
+    pub camera: (i32, i32, i32),
+    pub safe_zone: Option<WorldPos>,
+    pub revealed: u64,
+    pub clicks: u32,
+    /// Cells the view moved over.
+    pub distance: f32,
+    pub lives: u32,
+    /// Cells of a finite board, row by row. Empty for the infinite world.
+    pub cells: Vec<Cell>,
+    /// Cells the flood fill in progress is still to visit.
+    pub pending: Vec<WorldPos>,
+}
+
+impl SavedGame {
+    /// Captures `game`, flood fill in progress included.
+    /// Chunks of the infinite world are written to its save directory, failing if they can't be.
+    pub fn new(game: &mut Game, elapsed: u64, camera: (i32, i32, i32), distance: f32) -> io::Result<SavedGame> {
+        game.grid.save()?;
+        let cells = match game.grid.board() {
+            Some(size) => (0..size.height as i32)
+                .flat_map(|y| (0..size.width as i32).map(move |x| WorldPos::new(x, y)))
+                .map(|pos| *game.grid.get_cell(pos).unwrap())
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(SavedGame {
+            seed: game.grid.seed(),
+            config: game.config.clone(),
+            state: game.state(),
+            elapsed,
+            camera,
+            safe_zone: game.grid.safe_zone(),
+            revealed: game.grid.revealed_count(),
+            clicks: game.clicks(),
+            distance,
+            lives: game.lives(),
+            cells,
+            pending: game.grid.pending(),
+        })
+    }
+
+    /// The game as it was saved, the infinite world being read from `save_dir`.
+    pub fn restore(&self, save_dir: &Path) -> Game {
+        let mut grid = match self.config.board {
+            Some(size) => Grid::fixed_from_cells(self.seed, size, self.safe_zone, &self.cells),
+            None => {
+                let mut grid = Grid::persistent(self.seed, save_dir);
+                grid.set_revealed_count(self.revealed);
+                grid
+            },
+        };
+        grid.set_pending(&self.pending);
+        Game::restored(grid, self.config.clone(), self.state, self.clicks, self.lives)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(MAGIC);
+        result.extend_from_slice(&VERSION.to_le_bytes());
+        result.extend_from_slice(&self.seed.to_le_bytes());
+        self.config.encode(&mut result);
+        result.push(match self.state {
+            State::Playing => 0,
+            State::Lost => 1,
+            State::Won => 2,
+        });
+        result.extend_from_slice(&self.elapsed.to_le_bytes());
+        result.extend_from_slice(&self.revealed.to_le_bytes());
+        let (x, y, zoom) = self.camera;
+        for v in [x, y, zoom] {
+            result.extend_from_slice(&v.to_le_bytes());
+        }
+        let safe_zone = self.safe_zone.unwrap_or(WorldPos::new(0, 0));
+        result.push(self.safe_zone.is_some() as u8);
+        result.extend_from_slice(&safe_zone.x.to_le_bytes());
+        result.extend_from_slice(&safe_zone.y.to_le_bytes());
+        result.extend_from_slice(&self.clicks.to_le_bytes());
+        result.extend_from_slice(&self.distance.to_le_bytes());
+        result.extend_from_slice(&self.lives.to_le_bytes());
+        result.extend_from_slice(&(self.cells.len() as u32).to_le_bytes());
+        result.extend(self.cells.iter().map(|c| c.encode()));
+        result.extend_from_slice(&(self.pending.len() as u32).to_le_bytes());
+        for pos in &self.pending {
+            result.extend_from_slice(&pos.x.to_le_bytes());
+            result.extend_from_slice(&pos.y.to_le_bytes());
+        }
+        result
+    }
+
+    pub fn decode(data: &[u8]) -> Result<SavedGame, String> {
+        let mut reader = Reader::new(data);
+        reader.header(MAGIC, "save", VERSION)?;
+        let seed = reader.u64()?;
+        let config = Config::decode(&mut reader)?;
+        let state = match reader.u8()? {
+            0 => State::Playing,
+            1 => State::Lost,
+            2 => State::Won,
+            s => return Err(format!("unknown state {}", s)),
+        };
+        let elapsed = reader.u64()?;
+        let revealed = reader.u64()?;
+        let camera = (reader.i32()?, reader.i32()?, reader.i32()?);
+        let has_safe_zone = reader.bool()?;
+        let safe_zone = WorldPos::new(reader.i32()?, reader.i32()?);
+        let (clicks, distance) = (reader.u32()?, reader.f32()?);
+        let lives = reader.u32()?;
+        if lives == 0 && state == State::Playing {
+            return Err("no life left in a game still being played".to_string());
+        }
+        let count = reader.u32()? as usize;
+        let expected = config.board.map_or(0, |b| b.width as usize * b.height as usize);
+        if count != expected {
+            return Err(format!("{} cells saved for a board of {}", count, expected));
+        }
+        let cells = reader.bytes(count)?.iter().map(|b| Cell::decode(*b)).collect();
+        let mut pending = Vec::new();
+        for _ in 0..reader.u32()? {
+            pending.push(WorldPos::new(reader.i32()?, reader.i32()?));
+        }
+        if !reader.is_empty() {
+            return Err("unexpected data after the flood fill".to_string());
+        }
+        Ok(SavedGame {
+            seed,
+            config,
+            state,
+            elapsed,
+            camera,
+            safe_zone: has_safe_zone.then_some(safe_zone),
+            revealed,
+            clicks,
+            distance,
+            lives,
+            cells,
+            pending,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.encode())
+    }
+
+    pub fn load(path: &Path) -> Result<SavedGame, String> {
+        let data = fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        SavedGame::decode(&data).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::*;
+
+    #[test]
+    fn saved_board_carries_on() {
+        let mut game = Game::new(Grid::fixed(5, BoardSize::BEGINNER), Config { board: Some(BoardSize::BEGINNER), ..Config::default() });
+        game.reveal(WorldPos::new(4, 4));
+        let mine = (0..81).map(|i| WorldPos::new(i % 9, i / 9)).find(|p| game.grid.get_cell(*p).unwrap().is_mine()).unwrap();
+        game.flag(mine);
+        let saved = SavedGame::new(&mut game, 12_345, (-40, 8, 32), 3.5).unwrap();
+        // Numbers aren't saved, they are counted again
+        let decoded = SavedGame::decode(&saved.encode()).unwrap();
+        assert_eq!(decoded.encode(), saved.encode());
+
+        let restored = decoded.restore(Path::new("unused"));
+        assert_eq!(restored.state(), State::Playing);
+        assert_eq!(restored.grid.revealed_count(), game.grid.revealed_count());
+        assert_eq!(restored.clicks(), 2);
+        assert_eq!(restored.grid.safe_zone(), game.grid.safe_zone());
+        for i in 0..81 {
+            let pos = WorldPos::new(i % 9, i / 9);
+            assert_eq!(restored.grid.get_cell(pos), game.grid.get_cell(pos), "cell {:?}", pos);
+        }
+    }
+
+    #[test]
+    fn flood_fill_carries_on_after_loading() {
+        // Large enough for the opening to take more than one go
+        let size = BoardSize::custom(100, 100, 10).unwrap();
+        let config = Config { board: Some(size), ..Config::default() };
+        let mut game = Game::new(Grid::fixed(7, size), config.clone());
+        let mut finished = Game::new(Grid::fixed(7, size), config);
+        game.reveal(WorldPos::new(50, 50));
+        finished.reveal(WorldPos::new(50, 50));
+        assert!(game.grid.is_revealing());
+        while finished.grid.is_revealing() {
+            finished.update();
+        }
+        let saved = SavedGame::new(&mut game, 0, (0, 0, 16), 0.0).unwrap();
+        assert!(!saved.pending.is_empty());
+        let decoded = SavedGame::decode(&saved.encode()).unwrap();
+        assert_eq!(decoded.pending, saved.pending);
+        let mut restored = decoded.restore(Path::new("unused"));
+        while restored.grid.is_revealing() {
+            restored.update();
+        }
+        assert_eq!(restored.grid.revealed_count(), finished.grid.revealed_count());
+        for i in 0..100 * 100 {
+            let pos = WorldPos::new(i % 100, i / 100);
+            assert_eq!(restored.grid.get_cell(pos), finished.grid.get_cell(pos), "cell {:?}", pos);
+        }
+    }
+
+    #[test]
+    fn broken_saves_are_errors() {
+        let mut game = Game::new(Grid::fixed(5, BoardSize::BEGINNER), Config { board: Some(BoardSize::BEGINNER), ..Config::default() });
+        let data = SavedGame::new(&mut game, 0, (0, 0, 16), 0.0).unwrap().encode();
+        assert_eq!(SavedGame::decode(&data[..data.len() - 3]), Err("truncated".to_string()));
+        assert!(SavedGame::decode(b"MSRP\x02\x00").is_err());
+
+        let mut newer = data.clone();
+        newer[4] = 2;
+        assert_eq!(SavedGame::decode(&newer), Err("unsupported version 2".to_string()));
+    }
+
+}
@@ -0,0 +1,529 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::*;
+
+/// Cells the solver is sure about.
+#[derive(Default, Debug)]
+pub struct Deductions {
+    pub safe: Vec<WorldPos>,
+    pub mines: Vec<WorldPos>,
+    /// Mines held by each cell of `mines`, 1 unless mines are weighted.
+    pub weights: HashMap<WorldPos, i32>,
+}
+
+/// What the player sees of a cell.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Seen {
+    Hidden,
+    /// Flagged with that many mines, or a mine of that weight that blew up.
+    Flagged(i32),
+    Number(i32),
+    /// Off the board.
+    Outside,
+}
+
+impl Seen {
+    pub fn of(grid: &Grid, pos: WorldPos) -> Seen {
+        if !grid.contains(pos) {
+            return Seen::Outside;
+        }
+        // A cell of an unloaded chunk is as good as hidden
+        match grid.get_cell(pos) {
+            Some(c) if c.is_flagged() => Seen::Flagged(c.flag),
+            Some(c) if c.revealed => match c.contents {
+                CellContents::Empty(nb) => Seen::Number(nb),
+                CellContents::Mine(weight) => Seen::Flagged(weight),
+            },
+            _ => Seen::Hidden,
+        }
+    }
+}
+
+/// The mines of `cells` add up to exactly `mines`, each cell holding one of the weights a mine can have, or none.
+/// Cells are kept sorted so equal constraints compare equal.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct Constraint {
+    cells: Vec<WorldPos>,
+    mines: i32,
+}
+
+impl Constraint {
+    fn new(mut cells: Vec<WorldPos>, mines: i32) -> Constraint {
+        cells.sort_by_key(|p| (p.y, p.x));
+        Constraint { cells, mines }
+    }
+
+    fn len(&self) -> i32 {
+        self.cells.len() as i32
+    }
+}
+
+/// Works out safe cells and mines from what the player sees: revealed numbers, flags and hidden cells.
+/// Hidden contents are never looked at, and flags are trusted to be right.
+///
+/// Deductions go in three steps, each one only tried when the previous ones are stuck:
+/// - single cell: a number with as many hidden neighbours as missing mines, or no missing mine,
+/// - subset: when the cells of a constraint all belong to another one, the difference is a constraint too,
+/// - linked: two overlapping constraints bound the mines in their common cells, which can settle the rest.
+///
+/// With weighted mines, a cell holds any of `values`, and constraints are bounded by the lightest
+/// and the heaviest of them rather than by 0 and 1.
+pub struct Solver {
+    constraints: HashSet<Constraint>,
+    /// Mines held by the cells worked out, 0 for safe ones.
+    known: HashMap<WorldPos, i32>,
+    weights: MineWeights,
+    /// What a hidden cell can hold: no mine, or a mine of any weight, sorted.
+    values: Vec<i32>,
+}
+
+impl Solver {
+    /// Constraints of every revealed number of the loaded chunks.
+    pub fn new(grid: &Grid) -> Solver {
+        let numbers = grid.loaded_chunks()
+            .flat_map(|chunk| chunk.cells().map(|(local, _)| chunk.position().world(local)));
+        Solver::from_view(numbers, grid.topology(), grid.weights(), |pos| Seen::of(grid, pos))
+    }
+
+    /// Constraints of the numbers among `cells`, as told by `view`, of the neighbours given by `topology`.
+    pub fn from_view(
+        cells: impl IntoIterator<Item = WorldPos>,
+        topology: &dyn Topology,
+        weights: MineWeights,
+        view: impl Fn(WorldPos) -> Seen,
+    ) -> Solver {
+        let mut constraints = HashSet::new();
+        for pos in cells {
+            let Seen::Number(nb) = view(pos) else {
+                continue;
+            };
+            let mut hidden = Vec::new();
+            let mut flags = 0;
+            for p in topology.neighbours(pos) {
+                match view(p) {
+                    Seen::Hidden => hidden.push(p),
+                    Seen::Flagged(weight) => flags += weight,
+                    Seen::Number(_) | Seen::Outside => {},
+                }
+            }
+            if !hidden.is_empty() {
+                constraints.insert(Constraint::new(hidden, nb - flags));
+            }
+        }
+        let mut values = weights.values();
+        values.push(0);
+        values.sort();
+        Solver { constraints, known: HashMap::new(), weights, values }
+    }
+
+    /// Lightest and heaviest contents of a cell.
+    fn bounds(&self) -> (i32, i32) {
+        (self.values[0], *self.values.last().unwrap())
+    }
+
+    /// Every cell that can be deduced, in the order it was found.
+    pub fn solve(&mut self) -> Deductions {
+        let mut result = Deductions::default();
+        loop {
+            let (found, added) = match self.single_cells() {
+                found if found.is_empty() => self.pairs(),
+                found => (found, false),
+            };
+            // New subset constraints may lead to more, through single cells or other pairs
+            if found.is_empty() && !added {
+                break;
+            }
+            for (pos, mines) in found {
+                if self.known.insert(pos, mines).is_none() {
+                    if mines == 0 {
+                        result.safe.push(pos);
+                    } else {
+                        result.mines.push(pos);
+                        result.weights.insert(pos, mines);
+                    }
+                }
+            }
+            self.simplify();
+        }
+        result
+    }
+
+    /// Cells of a constraint that can only hold one of `values`, the others being within bounds.
+    fn single_cells(&self) -> Vec<(WorldPos, i32)> {
+        let (lo, hi) = self.bounds();
+        let mut result = Vec::new();
+        for c in &self.constraints {
+            let others = (c.len() - 1) * lo..=(c.len() - 1) * hi;
+            let mut fitting = self.values.iter().filter(|v| others.contains(&(c.mines - **v)));
+            if let (Some(v), None) = (fitting.next(), fitting.next()) {
+                result.extend(c.cells.iter().map(|p| (*p, *v)));
+            }
+        }
+        result
+    }
+
+    /// Subset and linked deductions over every pair of constraints sharing a cell.
+    /// New subset constraints are kept, cells settled by linked constraints are returned
+    /// along with whether any constraint was added.
+    fn pairs(&mut self) -> (Vec<(WorldPos, i32)>, bool) {
+        let constraints: Vec<Constraint> = self.constraints.iter().cloned().collect();
+        let mut by_cell: HashMap<WorldPos, Vec<usize>> = HashMap::new();
+        for (i, c) in constraints.iter().enumerate() {
+            for p in &c.cells {
+                by_cell.entry(*p).or_default().push(i);
+            }
+        }
+
+        let mut result = Vec::new();
+        let mut added = false;
+        let mut seen = HashSet::new();
+        for (i, a) in constraints.iter().enumerate() {
+            for p in &a.cells {
+                for &j in &by_cell[p] {
+                    if j == i || !seen.insert((i.min(j), i.max(j))) {
+                        continue;
+                    }
+                    let b = &constraints[j];
+                    let common = a.cells.iter().filter(|p| b.cells.contains(p)).count() as i32;
+                    if common == a.len() || common == b.len() {
+                        let (small, big) = if common == a.len() { (a, b) } else { (b, a) };
+                        let rest: Vec<WorldPos> = big.cells.iter().filter(|p| !small.cells.contains(p)).copied().collect();
+                        if !rest.is_empty() {
+                            added |= self.constraints.insert(Constraint::new(rest, big.mines - small.mines));
+                        }
+                    } else {
+                        result.extend(linked(a, b, common, self.bounds()));
+                    }
+                }
+            }
+        }
+        (result, added)
+    }
+
+    /// Removes known cells from the constraints.
+    fn simplify(&mut self) {
+        let old = std::mem::take(&mut self.constraints);
+        for mut c in old {
+            let mines: i32 = c.cells.iter().filter_map(|p| self.known.get(p)).sum();
+            c.cells.retain(|p| !self.known.contains_key(p));
+            c.mines -= mines;
+            if !c.cells.is_empty() {
+                self.constraints.insert(c);
+            }
+        }
+    }
+
+    /// Chance of being a mine of every hidden cell next to a number, after solving.
+    /// Groups of cells linked by constraints are worked out exactly by going through every
+    /// layout that fits, each weighted by how likely its number of mines is at `density`.
+    /// Groups too big for that get an estimate from the constraints they are part of.
+    /// With weighted mines, this is the chance of holding a mine of any weight.
+    pub fn probabilities(&mut self, density: f32) -> HashMap<WorldPos, f32> {
+        self.solve();
+        let mut result: HashMap<WorldPos, f32> = self.known.iter()
+            .map(|(p, mines)| (*p, if *mines != 0 { 1.0 } else { 0.0 }))
+            .collect();
+        // Chance of each contents of a cell against no mine at all
+        let odds: Vec<(i32, f64)> = self.values.iter()
+            .map(|v| {
+                let chance = if *v == 0 { 1.0 - density } else { density * self.weights.chance(*v) };
+                (*v, chance as f64 / (1.0 - density as f64))
+            })
+            .collect();
+        for group in self.groups() {
+            match enumerate(&group, &odds) {
+                Some(probabilities) => result.extend(probabilities),
+                None => {
+                    for c in &group {
+                        let p = (c.mines as f32 / c.len() as f32).clamp(0.0, 1.0);
+                        for pos in &c.cells {
+                            let e = result.entry(*pos).or_insert(p);
+                            *e = e.max(p);
+                        }
+                    }
+                },
+            }
+        }
+        result
+    }
+
+    /// Constraints split into groups that share no cell.
+    fn groups(&self) -> Vec<Vec<Constraint>> {
+        let mut by_cell: HashMap<WorldPos, Vec<&Constraint>> = HashMap::new();
+        for c in &self.constraints {
+            for p in &c.cells {
+                by_cell.entry(*p).or_default().push(c);
+            }
+        }
+        let mut seen = HashSet::new();
+        let mut result = Vec::new();
+        for c in &self.constraints {
+            if seen.contains(c) {
+                continue;
+            }
+            let mut group = Vec::new();
+            let mut pending = vec![c];
+            while let Some(c) = pending.pop() {
+                if !seen.insert(c.clone()) {
+                    continue;
+                }
+                pending.extend(c.cells.iter().flat_map(|p| by_cell[p].iter().copied()));
+                group.push(c.clone());
+            }
+            result.push(group);
+        }
+        result
+    }
+}
+
+/// Exact probabilities of a group of constraints, `None` if there are too many layouts to go through.
+/// `odds` tells how likely each contents of a cell is against no mine, the lightest first.
+fn enumerate(group: &[Constraint], odds: &[(i32, f64)]) -> Option<HashMap<WorldPos, f32>> {
+    // Visited states at most per group
+    const LIMIT: usize = 200_000;
+
+    struct Search<'a> {
+        group: &'a [Constraint],
+        cells: Vec<WorldPos>,
+        /// Constraints each cell is part of.
+        of_cell: Vec<Vec<usize>>,
+        /// Mines placed and cells left to decide, per constraint.
+        placed: Vec<(i32, i32)>,
+        mines: Vec<i32>,
+        odds: &'a [(i32, f64)],
+        total: f64,
+        weights: Vec<f64>,
+        visited: usize,
+    }
+
+    impl Search<'_> {
+        /// `weight` is how likely the cells placed so far are, against them holding no mine.
+        fn run(&mut self, i: usize, weight: f64) -> bool {
+            self.visited += 1;
+            if self.visited > LIMIT {
+                return false;
+            }
+            if i == self.cells.len() {
+                self.total += weight;
+                for (w, mines) in self.weights.iter_mut().zip(&self.mines) {
+                    if *mines != 0 {
+                        *w += weight;
+                    }
+                }
+                return true;
+            }
+            let (lo, hi) = (self.odds[0].0, self.odds[self.odds.len() - 1].0);
+            for &(mines, odds) in self.odds {
+                let fits = self.of_cell[i].iter().all(|c| {
+                    let (placed, left) = self.placed[*c];
+                    let rest = self.group[*c].mines - placed - mines;
+                    ((left - 1) * lo..=(left - 1) * hi).contains(&rest)
+                });
+                if !fits {
+                    continue;
+                }
+                for c in &self.of_cell[i] {
+                    self.placed[*c].0 += mines;
+                    self.placed[*c].1 -= 1;
+                }
+                self.mines[i] = mines;
+                let done = self.run(i + 1, weight * odds);
+                for c in &self.of_cell[i] {
+                    self.placed[*c].0 -= mines;
+                    self.placed[*c].1 += 1;
+                }
+                if !done {
+                    return false;
+                }
+            }
+            self.mines[i] = 0;
+            true
+        }
+    }
+
+    let mut cells: Vec<WorldPos> = group.iter().flat_map(|c| c.cells.iter().copied()).collect();
+    cells.sort_by_key(|p| (p.y, p.x));
+    cells.dedup();
+    let of_cell = cells.iter()
+        .map(|p| (0..group.len()).filter(|c| group[*c].cells.contains(p)).collect())
+        .collect();
+    let mut search = Search {
+        group,
+        of_cell,
+        placed: group.iter().map(|c| (0, c.len())).collect(),
+        mines: vec![0; cells.len()],
+        odds,
+        total: 0.0,
+        weights: vec![0.0; cells.len()],
+        visited: 0,
+        cells,
+    };
+    if !search.run(0, 1.0) || search.total == 0.0 {
+        return None;
+    }
+    Some(search.cells.iter().zip(&search.weights).map(|(p, w)| (*p, (w / search.total) as f32)).collect())
+}
+
+/// Settles the cells of two overlapping constraints outside their common part, if every possible
+/// number of mines in the common part agrees on them. Cells hold between `lo` and `hi` mines.
+fn linked(a: &Constraint, b: &Constraint, common: i32, (lo, hi): (i32, i32)) -> Vec<(WorldPos, i32)> {
+    let only_a = a.len() - common;
+    let only_b = b.len() - common;
+    let feasible: Vec<i32> = (common * lo..=common * hi)
+        .filter(|c| (only_a * lo..=only_a * hi).contains(&(a.mines - c)) && (only_b * lo..=only_b * hi).contains(&(b.mines - c)))
+        .collect();
+    let mut result = Vec::new();
+    if feasible.is_empty() {
+        // Contradiction, a flag must be wrong
+        return result;
+    }
+    for (this, other, only) in [(a, b, only_a), (b, a, only_b)] {
+        let mines = if feasible.iter().all(|c| this.mines - c == only * lo) {
+            lo
+        } else if feasible.iter().all(|c| this.mines - c == only * hi) {
+            hi
+        } else {
+            continue;
+        };
+        result.extend(this.cells.iter().filter(|p| !other.cells.contains(p)).map(|p| (*p, mines)));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::*;
+
+    /// A grid with no mine, with the mines given as (x, y) added and `revealed` opened.
+    fn board(mines: &[(i32, i32)], revealed: &[(i32, i32)]) -> Grid {
+        let mines: Vec<_> = mines.iter().map(|m| (*m, 1)).collect();
+        weighted_board(MineWeights::default(), &mines, revealed)
+    }
+
+    /// Same as `board`, each mine being given with its weight.
+    fn weighted_board(weights: MineWeights, mines: &[((i32, i32), i32)], revealed: &[(i32, i32)]) -> Grid {
+        let mut grid = Grid::fixed(0, BoardSize { width: 5, height: 3, mines: 0 });
+        grid.set_weights(weights);
+        for ((x, y), weight) in mines {
+            grid.get_cell_mut(WorldPos::new(*x, *y)).unwrap().contents = CellContents::Mine(*weight);
+        }
+        for y in 0..3 {
+            for x in 0..5 {
+                let pos = WorldPos::new(x, y);
+                if grid.get_cell(pos).unwrap().is_mine() {
+                    continue;
+                }
+                let nb = NEIGHBOURS.iter()
+                    .map(|o| match grid.get_cell(pos + *o).map(|c| c.contents) {
+                        Some(CellContents::Mine(weight)) => weight,
+                        _ => 0,
+                    })
+                    .sum();
+                grid.get_cell_mut(pos).unwrap().contents = CellContents::Empty(nb);
+            }
+        }
+        for (x, y) in revealed {
+            grid.get_cell_mut(WorldPos::new(*x, *y)).unwrap().revealed = true;
+        }
+        grid
+    }
+
+    #[test]
+    fn one_two_pattern() {
+        //   ? ? ? ? ?    mines at (1, 0) and (2, 0)
+        //   1 2 2 1 ?
+        //   0 0 0 0 0
+        let grid = board(&[(1, 0), (2, 0)], &[(0, 1), (1, 1), (2, 1), (3, 1), (0, 2), (1, 2), (2, 2), (3, 2), (4, 2)]);
+        let result = Solver::new(&grid).solve();
+        for pos in &result.mines {
+            assert!(grid.get_cell(*pos).unwrap().is_mine());
+        }
+        for pos in &result.safe {
+            assert!(!grid.get_cell(*pos).unwrap().is_mine());
+        }
+        assert!(result.mines.contains(&WorldPos::new(1, 0)));
+        assert!(result.mines.contains(&WorldPos::new(2, 0)));
+        assert!(result.safe.contains(&WorldPos::new(0, 0)));
+        assert!(result.safe.contains(&WorldPos::new(3, 0)));
+    }
+
+    #[test]
+    fn subset_deductions_chain() {
+        // {a, b} hold 1 mine, {a, b, c, d, e} 2 and {c, d, e, f} 1: the first two leave 1 mine
+        // in {c, d, e}, which only then tells that f is safe
+        let [a, b, c, d, e, f] = [0, 1, 2, 3, 4, 5].map(|x| WorldPos::new(x, 0));
+        let constraints = [(vec![a, b], 1), (vec![a, b, c, d, e], 2), (vec![c, d, e, f], 1)];
+        let mut solver = Solver {
+            constraints: constraints.into_iter().map(|(cells, mines)| super::Constraint::new(cells, mines)).collect(),
+            known: HashMap::new(),
+            weights: MineWeights::default(),
+            values: vec![0, 1],
+        };
+        let result = solver.solve();
+        assert_eq!(result.safe, vec![f]);
+        assert!(result.mines.is_empty());
+    }
+
+    #[test]
+    fn weighted_mines() {
+        //   ? ? ? ? ?    2 mines at (1, 0), 1 at (3, 0)
+        //   2 2 3 1 1
+        //   0 0 0 0 0
+        let revealed = [(0, 1), (1, 1), (2, 1), (3, 1), (4, 1), (0, 2), (1, 2), (2, 2), (3, 2), (4, 2)];
+        let doubles = MineWeights { double: 0.3, ..MineWeights::default() };
+        let grid = weighted_board(doubles, &[((1, 0), 2), ((3, 0), 1)], &revealed);
+        // (3, 1) and (4, 1) need a single mine in common with (2, 1), which leaves 2 on (1, 0)
+        let result = Solver::new(&grid).solve();
+        assert_eq!(result.weights, HashMap::from([(WorldPos::new(1, 0), 2), (WorldPos::new(3, 0), 1)]));
+        assert_eq!(result.safe.len(), 3);
+
+        //   ? ? ? ? ?    negative mines at (0, 0) and (1, 0)
+        //  -2-2-1 0 0
+        //   0 0 0 0 0
+        let negatives = MineWeights { negative: 0.5, ..MineWeights::default() };
+        let grid = weighted_board(negatives, &[((0, 0), -1), ((1, 0), -1)], &revealed);
+        let result = Solver::new(&grid).solve();
+        assert_eq!(result.weights, HashMap::from([(WorldPos::new(0, 0), -1), (WorldPos::new(1, 0), -1)]));
+        assert_eq!(result.safe.len(), 3);
+        let probabilities = Solver::new(&grid).probabilities(0.2);
+        assert_eq!(probabilities[&WorldPos::new(1, 0)], 1.0);
+    }
+
+    #[test]
+    fn probabilities_of_a_lone_number() {
+        //   ? ? ? ? ?    a mine at (1, 0)
+        //   1 1 1 ? ?
+        //   0 0 0 ? ?
+        let grid = board(&[(1, 0)], &[(0, 1), (1, 1), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        let probabilities = Solver::new(&grid).probabilities(0.1);
+        // (2, 2) clears (3, 1) and (3, 2), (0, 1) and (1, 1) clear (2, 0),
+        // then either (1, 0) alone is a mine, or both (0, 0) and (3, 0) are
+        let p = |x, y| probabilities[&WorldPos::new(x, y)];
+        assert_eq!(p(2, 0), 0.0);
+        assert_eq!(p(3, 1), 0.0);
+        assert_eq!(p(0, 0), p(3, 0));
+        assert!((p(0, 0) + p(1, 0) - 1.0).abs() < 1e-5);
+        // One mine is more likely than two at a low density
+        assert!((p(1, 0) - 0.9).abs() < 1e-5);
+        assert!(!probabilities.contains_key(&WorldPos::new(4, 0)));
+    }
+
+    #[test]
+    fn deductions_are_right() {
+        let weighted = Config { weights: "0.2/0.1/0.2".parse().unwrap(), ..Config::default() };
+        for (seed, config) in (0..20).flat_map(|seed| [(seed, Config::default()), (seed, weighted.clone())]) {
+            let mut game = Game::new(Grid::fixed(seed, BoardSize::INTERMEDIATE), config);
+            game.reveal(WorldPos::new(8, 8));
+            let result = Solver::new(&game.grid).solve();
+            for pos in &result.mines {
+                let cell = game.grid.get_cell(*pos).unwrap();
+                assert_eq!(cell.contents, CellContents::Mine(result.weights[pos]));
+            }
+            for pos in &result.safe {
+                assert!(!game.grid.get_cell(*pos).unwrap().is_mine());
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Statistics of the games played, per mode, kept in a profile file. All integers are little endian.
+//!
+//! ```text
+//! header: magic "MSST" | version: u16 | mode count: u32
+//! mode:   name length: u16 | name in utf-8 | played: u32 | won: u32 | streak: u32 | best streak: u32
+//!         clicks: u64 | cells revealed: u64 | distance: f32 | best 3BV/s: f32
+//!         time won in ms: u64 | 3BV won: u64 | clicks won: u64 | best game count: u8 | best games
+//! game:   time in ms: u64 | 3BV: u32 | clicks: u32 | cells revealed: u64 | distance: f32 | date: u64
+//! ```
+//!
+//! Modes are named by `Config::mode`. Dates are in seconds since the Unix epoch.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::*;
+use crate::codec::Reader;
+
+const MAGIC: &[u8; 4] = b"MSST";
+const VERSION: u16 = 1;
+/// Games kept in the high score table of each mode.
+pub const HIGH_SCORES: usize = 10;
+
+/// How a game went.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GameResult {
+    pub won: bool,
+    /// Milliseconds spent playing.
+    pub time: u64,
+    /// 3BV of a finite board, 0 for the infinite world.
+    pub bbbv: u32,
+    pub clicks: u32,
+    pub revealed: u64,
+    /// Cells the view moved over.
+    pub distance: f32,
+    pub date: u64,
+}
+
+impl GameResult {
+    /// `game` as it ended, after `time` ms of play with the view moving over `distance` cells.
+    pub fn new(game: &Game, time: u64, distance: f32, date: u64) -> GameResult {
+        GameResult {
+            won: game.state() == State::Won,
+            time,
+            bbbv: Metrics::of_board(&game.grid).map_or(0, |m| m.bbbv),
+            clicks: game.clicks(),
+            revealed: game.grid.revealed_count(),
+            distance,
+            date,
+        }
+    }
+
+    pub fn bbbv_per_second(&self) -> f32 {
+        if self.time == 0 { 0.0 } else { self.bbbv as f32 * 1000.0 / self.time as f32 }
+    }
+
+    /// 3BV per click, 1 meaning no click was wasted.
+    pub fn efficiency(&self) -> f32 {
+        self.bbbv as f32 / self.clicks.max(1) as f32
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.time.to_le_bytes());
+        out.extend_from_slice(&self.bbbv.to_le_bytes());
+        out.extend_from_slice(&self.clicks.to_le_bytes());
+        out.extend_from_slice(&self.revealed.to_le_bytes());
+        out.extend_from_slice(&self.distance.to_le_bytes());
+        out.extend_from_slice(&self.date.to_le_bytes());
+    }
+
+    /// Only games won are stored.
+    fn decode(reader: &mut Reader) -> Result<GameResult, String> {
+        Ok(GameResult {
+            won: true,
+            time: reader.u64()?,
+            bbbv: reader.u32()?,
+            clicks: reader.u32()?,
+            revealed: reader.u64()?,
+            distance: reader.f32()?,
+            date: reader.u64()?,
+        })
+    }
+}
+
+/// Statistics of the games of a mode.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModeStats {
+    pub played: u32,
+    pub won: u32,
+    /// Games won in a row, up to the last one.
+    pub streak: u32,
+    pub best_streak: u32,
+    pub clicks: u64,
+    pub revealed: u64,
+    pub distance: f32,
+    pub best_bbbv_per_second: f32,
+    /// Totals over the games won, for averages.
+    pub won_time: u64,
+    pub won_bbbv: u64,
+    pub won_clicks: u64,
+    /// Fastest games won, the fastest first.
+    pub best: Vec<GameResult>,
+}
+
+impl ModeStats {
+    pub fn add(&mut self, game: &GameResult) {
+        self.played += 1;
+        self.clicks += game.clicks as u64;
+        self.revealed += game.revealed;
+        self.distance += game.distance;
+        if !game.won {
+            self.streak = 0;
+            return;
+        }
+        self.won += 1;
+        self.streak += 1;
+        self.best_streak = self.best_streak.max(self.streak);
+        self.best_bbbv_per_second = self.best_bbbv_per_second.max(game.bbbv_per_second());
+        self.won_time += game.time;
+        self.won_bbbv += game.bbbv as u64;
+        self.won_clicks += game.clicks as u64;
+        let rank = self.best.iter().position(|g| g.time > game.time).unwrap_or(self.best.len());
+        self.best.insert(rank, *game);
+        self.best.truncate(HIGH_SCORES);
+    }
+
+    /// Time of the fastest game won, in ms.
+    pub fn best_time(&self) -> Option<u64> {
+        self.best.first().map(|g| g.time)
+    }
+
+    pub fn win_rate(&self) -> f32 {
+        self.won as f32 / self.played.max(1) as f32
+    }
+
+    /// Average 3BV/s of the games won.
+    pub fn bbbv_per_second(&self) -> f32 {
+        if self.won_time == 0 { 0.0 } else { self.won_bbbv as f32 * 1000.0 / self.won_time as f32 }
+    }
+
+    /// Average efficiency of the games won.
+    pub fn efficiency(&self) -> f32 {
+        self.won_bbbv as f32 / self.won_clicks.max(1) as f32
+    }
+}
+
+/// Statistics of every mode played.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    pub modes: BTreeMap<String, ModeStats>,
+}
+
+impl Profile {
+    pub fn record(&mut self, mode: &str, game: &GameResult) {
+        self.modes.entry(mode.to_string()).or_default().add(game);
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(MAGIC);
+        result.extend_from_slice(&VERSION.to_le_bytes());
+        result.extend_from_slice(&(self.modes.len() as u32).to_le_bytes());
+        for (name, s) in &self.modes {
+            result.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            result.extend_from_slice(name.as_bytes());
+            for v in [s.played, s.won, s.streak, s.best_streak] {
+                result.extend_from_slice(&v.to_le_bytes());
+            }
+            result.extend_from_slice(&s.clicks.to_le_bytes());
+            result.extend_from_slice(&s.revealed.to_le_bytes());
+            result.extend_from_slice(&s.distance.to_le_bytes());
+            result.extend_from_slice(&s.best_bbbv_per_second.to_le_bytes());
+            for v in [s.won_time, s.won_bbbv, s.won_clicks] {
+                result.extend_from_slice(&v.to_le_bytes());
+            }
+            result.push(s.best.len() as u8);
+            for g in &s.best {
+                g.encode(&mut result);
+            }
+        }
+        result
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Profile, String> {
+        let mut reader = Reader::new(data);
+        reader.header(MAGIC, "profile", VERSION)?;
+        let mut modes = BTreeMap::new();
+        for _ in 0..reader.u32()? {
+            let name = reader.string()?;
+            let mut stats = ModeStats {
+                played: reader.u32()?,
+                won: reader.u32()?,
+                streak: reader.u32()?,
+                best_streak: reader.u32()?,
+                clicks: reader.u64()?,
+                revealed: reader.u64()?,
+                distance: reader.f32()?,
+                best_bbbv_per_second: reader.f32()?,
+                won_time: reader.u64()?,
+                won_bbbv: reader.u64()?,
+                won_clicks: reader.u64()?,
+                best: Vec::new(),
+            };
+            for _ in 0..reader.u8()? {
+                stats.best.push(GameResult::decode(&mut reader)?);
+            }
+            modes.insert(name, stats);
+        }
+        if !reader.is_empty() {
+            return Err("unexpected data after the last mode".to_string());
+        }
+        Ok(Profile { modes })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.encode())
+    }
+
+    /// The profile saved at `path`, empty if there is none yet.
+    pub fn load(path: &Path) -> Result<Profile, String> {
+        if !path.exists() {
+            return Ok(Profile::default());
+        }
+        let data = fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        Profile::decode(&data).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Every statistic, as an object with one member per mode.
+    pub fn to_json(&self) -> String {
+        let mut result = "{".to_string();
+        for (i, (name, s)) in self.modes.iter().enumerate() {
+            let best_time = s.best_time().map_or("null".to_string(), |t| t.to_string());
+            let _ = write!(
+                result,
+                "{}\n  \"{}\": {{\"played\": {}, \"won\": {}, \"win_rate\": {:.3}, \"streak\": {}, \"best_streak\": {}, \
+                 \"best_time_ms\": {}, \"bbbv_per_second\": {:.3}, \"best_bbbv_per_second\": {:.3}, \"efficiency\": {:.3}, \
+                 \"clicks\": {}, \"cells_revealed\": {}, \"distance\": {:.1}, \"best_games\": [",
+                if i == 0 { "" } else { "," },
+                name.replace('\\', "\\\\").replace('"', "\\\""),
+                s.played, s.won, s.win_rate(), s.streak, s.best_streak,
+                best_time, s.bbbv_per_second(), s.best_bbbv_per_second, s.efficiency(),
+                s.clicks, s.revealed, s.distance,
+            );
+            for (j, g) in s.best.iter().enumerate() {
+                let _ = write!(
+                    result,
+                    "{}{{\"time_ms\": {}, \"bbbv\": {}, \"clicks\": {}, \"bbbv_per_second\": {:.3}, \"efficiency\": {:.3}, \"date\": {}}}",
+                    if j == 0 { "" } else { ", " },
+                    g.time, g.bbbv, g.clicks, g.bbbv_per_second(), g.efficiency(), g.date,
+                );
+            }
+            result += "]}";
+        }
+        result += "\n}\n";
+        result
+    }
+
+    /// One line per mode, after a line of column names.
+    pub fn to_csv(&self) -> String {
+        let mut result = "mode,played,won,win_rate,streak,best_streak,best_time_ms,bbbv_per_second,\
+                          best_bbbv_per_second,efficiency,clicks,cells_revealed,distance\n".to_string();
+        for (name, s) in &self.modes {
+            let _ = writeln!(
+                result,
+                "{},{},{},{:.3},{},{},{},{:.3},{:.3},{:.3},{},{},{:.1}",
+                name, s.played, s.won, s.win_rate(), s.streak, s.best_streak,
+                s.best_time().map_or(String::new(), |t| t.to_string()),
+                s.bbbv_per_second(), s.best_bbbv_per_second, s.efficiency(),
+                s.clicks, s.revealed, s.distance,
+            );
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn result(won: bool, time: u64) -> GameResult {
+        GameResult { won, time, bbbv: 30, clicks: 40, revealed: 71, distance: 0.0, date: 1_700_000_000 }
+    }
+
+    #[test]
+    fn streaks_and_best_times() {
+        let mut profile = Profile::default();
+        for (won, time) in [(true, 9000), (true, 5000), (false, 2000), (true, 7000)] {
+            profile.record("beginner", &result(won, time));
+        }
+        let stats = &profile.modes["beginner"];
+        assert_eq!((stats.played, stats.won, stats.streak, stats.best_streak), (4, 3, 1, 2));
+        assert_eq!(stats.best.iter().map(|g| g.time).collect::<Vec<_>>(), vec![5000, 7000, 9000]);
+        assert_eq!(stats.best_time(), Some(5000));
+        assert_eq!(stats.best_bbbv_per_second, 6.0);
+        assert_eq!(stats.efficiency(), 0.75);
+
+        assert_eq!(Profile::decode(&profile.encode()), Ok(profile.clone()));
+        let data = profile.encode();
+        assert_eq!(Profile::decode(&data[..data.len() - 1]), Err("truncated".to_string()));
+
+        assert!(profile.to_json().contains("\"beginner\": {\"played\": 4, \"won\": 3"));
+        assert!(profile.to_csv().lines().nth(1).unwrap().starts_with("beginner,4,3,0.750,1,2,5000,"));
+    }
+}
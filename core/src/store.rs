@@ -0,0 +1,318 @@
+//! On-disk storage for chunks that have been evicted from the `Grid`.
+//!
+//! Chunks are grouped in square regions of `REGION_SIZE` x `REGION_SIZE` chunks, one file per region,
+//! named `r.<x>.<y>.bin` inside the world's save directory. All integers are little endian.
+//!
+//! ```text
+//! header:  magic "MSRG" | version: u16 | seed: u64 | chunk count: u32
+//! chunk:   x: i32 | y: i32 | CHUNK_LEN cell bytes
+//! cell:    bit 0 revealed | bit 1 flag | bit 2 mine | bits 3..5 mine weight | bits 5..7 flag weight | bit 7 unused
+//! ```
+//!
+//! Weights are 0 for 1 mine, 1 for 2 mines, 2 for 3 mines and 3 for a negative mine,
+//! so cells written before mines had weights read as single mines.
+//!
+//! Only chunks the player has touched are written, untouched ones are regenerated from the seed.
+//! Neighbour counts aren't stored: they depend on the surrounding chunks and are recomputed on load.
+//! A file that can't be decoded is moved aside to `<name>.bad` rather than written over.
+//!
+//! What's needed to regenerate chunks besides the seed is kept in `world.bin`:
+//!
+//! ```text
+//! header:     magic "MSWD" | version: u16 | seed: u64
+//! safe zone:  present: u8 | x: i32 | y: i32
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::*;
+
+const MAGIC: &[u8; 4] = b"MSRG";
+const WORLD_MAGIC: &[u8; 4] = b"MSWD";
+const WORLD_LEN: usize = 4 + 2 + 8 + 1 + 4 + 4;
+const VERSION: u16 = 1;
+const REGION_SIZE: i32 = 8;
+const HEADER_LEN: usize = 4 + 2 + 8 + 4;
+const ENTRY_LEN: usize = 4 + 4 + CHUNK_LEN;
+
+const CELL_REVEALED: u8 = 1 << 0;
+const CELL_FLAG: u8 = 1 << 1;
+const CELL_MINE: u8 = 1 << 2;
+const CELL_MINE_WEIGHT: u8 = 3;
+const CELL_FLAG_WEIGHT: u8 = 5;
+const WEIGHTS: [i32; 4] = [1, 2, 3, -1];
+
+fn encode_weight(weight: i32) -> u8 {
+    WEIGHTS.iter().position(|w| *w == weight).unwrap_or(0) as u8
+}
+
+impl Cell {
+    pub fn encode(&self) -> u8 {
+        let mut result = 0;
+        if self.revealed { result |= CELL_REVEALED; }
+        if self.is_flagged() { result |= CELL_FLAG | encode_weight(self.flag) << CELL_FLAG_WEIGHT; }
+        if let CellContents::Mine(weight) = self.contents { result |= CELL_MINE | encode_weight(weight) << CELL_MINE_WEIGHT; }
+        result
+    }
+
+    pub fn decode(byte: u8) -> Cell {
+        Cell {
+            revealed: byte & CELL_REVEALED != 0,
+            flag: if byte & CELL_FLAG != 0 { WEIGHTS[(byte >> CELL_FLAG_WEIGHT & 3) as usize] } else { 0 },
+            contents: if byte & CELL_MINE != 0 {
+                CellContents::Mine(WEIGHTS[(byte >> CELL_MINE_WEIGHT & 3) as usize])
+            } else {
+                CellContents::Empty(0)
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct Region {
+    chunks: HashMap<ChunkPos, [u8; CHUNK_LEN]>,
+    dirty: bool,
+}
+
+impl Region {
+    fn encode(&self, seed: u64) -> Vec<u8> {
+        let mut result = Vec::with_capacity(HEADER_LEN + self.chunks.len() * ENTRY_LEN);
+        result.extend_from_slice(MAGIC);
+        result.extend_from_slice(&VERSION.to_le_bytes());
+        result.extend_from_slice(&seed.to_le_bytes());
+        result.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+        for (pos, cells) in &self.chunks {
+            result.extend_from_slice(&pos.x.to_le_bytes());
+            result.extend_from_slice(&pos.y.to_le_bytes());
+            result.extend_from_slice(cells);
+        }
+        result
+    }
+
+    fn decode(data: &[u8], seed: u64) -> Result<Region, String> {
+        if data.len() < HEADER_LEN || &data[0..4] != MAGIC {
+            return Err("not a region file".to_string());
+        }
+        let version = u16::from_le_bytes(data[4..6].try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("unsupported version {}", version));
+        }
+        let file_seed = u64::from_le_bytes(data[6..14].try_into().unwrap());
+        if file_seed != seed {
+            return Err(format!("belongs to world {}", file_seed));
+        }
+        let count = u32::from_le_bytes(data[14..18].try_into().unwrap()) as usize;
+        if data.len() != HEADER_LEN + count * ENTRY_LEN {
+            return Err("truncated".to_string());
+        }
+        let mut result = Region::default();
+        for entry in data[HEADER_LEN..].chunks_exact(ENTRY_LEN) {
+            let x = i32::from_le_bytes(entry[0..4].try_into().unwrap());
+            let y = i32::from_le_bytes(entry[4..8].try_into().unwrap());
+            result.chunks.insert(ChunkPos::new(x, y), entry[8..].try_into().unwrap());
+        }
+        Ok(result)
+    }
+}
+
+/// Generation parameters decided while playing.
+#[derive(Default, Debug, PartialEq)]
+pub struct WorldInfo {
+    pub safe_zone: Option<WorldPos>,
+}
+
+impl WorldInfo {
+    fn encode(&self, seed: u64) -> Vec<u8> {
+        let mut result = Vec::with_capacity(WORLD_LEN);
+        result.extend_from_slice(WORLD_MAGIC);
+        result.extend_from_slice(&VERSION.to_le_bytes());
+        result.extend_from_slice(&seed.to_le_bytes());
+        let safe_zone = self.safe_zone.unwrap_or(WorldPos::new(0, 0));
+        result.push(self.safe_zone.is_some() as u8);
+        result.extend_from_slice(&safe_zone.x.to_le_bytes());
+        result.extend_from_slice(&safe_zone.y.to_le_bytes());
+        result
+    }
+
+    fn decode(data: &[u8], seed: u64) -> Result<WorldInfo, String> {
+        if data.len() != WORLD_LEN || &data[0..4] != WORLD_MAGIC {
+            return Err("not a world file".to_string());
+        }
+        let version = u16::from_le_bytes(data[4..6].try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("unsupported version {}", version));
+        }
+        let file_seed = u64::from_le_bytes(data[6..14].try_into().unwrap());
+        if file_seed != seed {
+            return Err(format!("belongs to world {}", file_seed));
+        }
+        let x = i32::from_le_bytes(data[15..19].try_into().unwrap());
+        let y = i32::from_le_bytes(data[19..23].try_into().unwrap());
+        Ok(WorldInfo {
+            safe_zone: if data[14] != 0 { Some(WorldPos::new(x, y)) } else { None },
+        })
+    }
+}
+
+pub struct ChunkStore {
+    dir: PathBuf,
+    seed: u64,
+    regions: HashMap<(i32, i32), Region>,
+}
+
+impl ChunkStore {
+    pub fn new(dir: &Path, seed: u64) -> ChunkStore {
+        ChunkStore {
+            dir: dir.to_path_buf(),
+            seed,
+            regions: HashMap::new(),
+        }
+    }
+
+    fn region_of(chunk: ChunkPos) -> (i32, i32) {
+        (chunk.x.div_euclid(REGION_SIZE), chunk.y.div_euclid(REGION_SIZE))
+    }
+
+    fn region_path(&self, region: (i32, i32)) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.bin", region.0, region.1))
+    }
+
+    /// The region, read from its file the first time. A region that can't be read is left out
+    /// until it can, so that its file isn't written over.
+    fn region(&mut self, region: (i32, i32)) -> io::Result<&mut Region> {
+        if !self.regions.contains_key(&region) {
+            let path = self.region_path(region);
+            let loaded = match read_file(&path)? {
+                Some(data) => Region::decode(&data, self.seed).map_err(|e| set_aside(&path, e))?,
+                None => Region::default(),
+            };
+            self.regions.insert(region, loaded);
+        }
+        Ok(self.regions.get_mut(&region).unwrap())
+    }
+
+    pub fn store(&mut self, chunk: &Chunk) -> io::Result<()> {
+        let mut cells = [0u8; CHUNK_LEN];
+        for (byte, (_, cell)) in cells.iter_mut().zip(chunk.cells()) {
+            *byte = cell.encode();
+        }
+        let region = self.region(ChunkStore::region_of(chunk.position()))?;
+        region.chunks.insert(chunk.position(), cells);
+        region.dirty = true;
+        Ok(())
+    }
+
+    pub fn load(&mut self, position: ChunkPos) -> io::Result<Option<Chunk>> {
+        let region = self.region(ChunkStore::region_of(position))?;
+        let Some(cells) = region.chunks.get(&position) else {
+            return Ok(None);
+        };
+        let mut elems = [Cell::default(); CHUNK_LEN];
+        for (cell, byte) in elems.iter_mut().zip(cells.iter()) {
+            *cell = Cell::decode(*byte);
+        }
+        Ok(Some(Chunk::from_cells(position, elems)))
+    }
+
+    pub fn load_info(&self) -> io::Result<WorldInfo> {
+        let path = self.dir.join("world.bin");
+        match read_file(&path)? {
+            Some(data) => WorldInfo::decode(&data, self.seed).map_err(|e| set_aside(&path, e)),
+            None => Ok(WorldInfo::default()),
+        }
+    }
+
+    pub fn save_info(&self, info: &WorldInfo) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join("world.bin"), info.encode(self.seed))
+    }
+
+    /// Writes every region that changed since the last flush.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.regions.values().any(|r| r.dirty) {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+        for (pos, region) in &mut self.regions {
+            if !region.dirty {
+                continue;
+            }
+            let path = self.dir.join(format!("r.{}.{}.bin", pos.0, pos.1));
+            fs::write(path, region.encode(self.seed))?;
+            region.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+fn read_file(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(io::Error::new(e.kind(), format!("unable to read {}: {}", path.display(), e))),
+    };
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).map_err(|e| io::Error::new(e.kind(), format!("unable to read {}: {}", path.display(), e)))?;
+    Ok(Some(data))
+}
+
+/// Moves the file at `path`, which couldn't be decoded, to `<path>.bad`, telling why.
+fn set_aside(path: &Path, error: String) -> io::Error {
+    let mut bad = path.as_os_str().to_owned();
+    bad.push(".bad");
+    match fs::rename(path, &bad) {
+        Ok(()) => io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}, moved to {}", path.display(), error, Path::new(&bad).display())),
+        Err(e) => io::Error::new(e.kind(), format!("{}: {}, unable to move it aside: {}", path.display(), error, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::store::*;
+
+    #[test]
+    fn region_round_trip() {
+        let mut cells = [0u8; CHUNK_LEN];
+        cells[0] = Cell { revealed: true, flag: 0, contents: CellContents::Empty(3) }.encode();
+        cells[1] = Cell { revealed: false, flag: 1, contents: CellContents::Mine(1) }.encode();
+        cells[2] = Cell { revealed: false, flag: -1, contents: CellContents::Mine(3) }.encode();
+        let mut region = Region::default();
+        region.chunks.insert(ChunkPos::new(-2, 5), cells);
+
+        let decoded = Region::decode(&region.encode(7), 7).unwrap();
+        let decoded = decoded.chunks.get(&ChunkPos::new(-2, 5)).unwrap();
+        let a = Cell::decode(decoded[0]);
+        let b = Cell::decode(decoded[1]);
+        assert!(a.revealed && !a.is_flagged() && matches!(a.contents, CellContents::Empty(_)));
+        assert!(!b.revealed && b.flag == 1 && b.contents == CellContents::Mine(1));
+        // Weights, and single mines from before them
+        assert_eq!(Cell::decode(decoded[2]), Cell { revealed: false, flag: -1, contents: CellContents::Mine(3) });
+        assert_eq!(Cell::decode(CELL_FLAG | CELL_MINE), Cell { revealed: false, flag: 1, contents: CellContents::Mine(1) });
+
+        assert!(Region::decode(&region.encode(7), 8).is_err());
+        assert!(Region::decode(&region.encode(7)[..20], 7).is_err());
+    }
+
+    #[test]
+    fn broken_region_is_set_aside() {
+        let dir = std::env::temp_dir().join(format!("minesweeper-broken-region-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("r.0.0.bin"), b"MSRG broken").unwrap();
+        let mut store = ChunkStore::new(&dir, 7);
+        let error = store.load(ChunkPos::new(1, 1)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(dir.join("r.0.0.bin.bad")).unwrap(), b"MSRG broken");
+        assert!(store.load(ChunkPos::new(1, 1)).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn world_info_round_trip() {
+        let info = WorldInfo { safe_zone: Some(WorldPos::new(-4, 12)) };
+        assert_eq!(WorldInfo::decode(&info.encode(3), 3), Ok(info));
+        assert_eq!(WorldInfo::decode(&WorldInfo::default().encode(3), 3), Ok(WorldInfo::default()));
+    }
+}
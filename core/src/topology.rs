@@ -0,0 +1,373 @@
+//! Shapes of the cells and how they fit together. Cells are addressed by a `WorldPos` whatever their shape,
+//! a topology tells which cells touch and where each one lies in the plane, in cells: a square cell is 1 wide.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::*;
+
+/// How cells are laid out.
+/// In the infinite world, neighbours are never more than `Neighbourhood::MAX_REACH` cells away in either
+/// coordinate, so they are always in the chunk of the cell or in one of the 8 chunks around it.
+/// Topologies of finite boards may reach anywhere on the board.
+/// Topologies are `Send` so that a game can be played on another thread, e.g. by a server.
+pub trait Topology: Send {
+    /// Cells counted in the number of the cell at `pos`.
+    fn neighbours(&self, pos: WorldPos) -> Vec<WorldPos>;
+
+    /// Cell containing the point (x, y).
+    fn cell_at(&self, x: f32, y: f32) -> WorldPos;
+
+    /// Corners of the cell at `pos`, going around it.
+    fn polygon(&self, pos: WorldPos) -> Vec<(f32, f32)>;
+
+    /// Middle of the cell at `pos`.
+    fn center(&self, pos: WorldPos) -> (f32, f32) {
+        let corners = self.polygon(pos);
+        let (x, y) = corners.iter().fold((0.0, 0.0), |(x, y), c| (x + c.0, y + c.1));
+        (x / corners.len() as f32, y / corners.len() as f32)
+    }
+
+    /// The cell at `pos` and its neighbours, e.g. the area kept free of mines around the first click.
+    fn area(&self, pos: WorldPos) -> Vec<WorldPos> {
+        let mut result = vec![pos];
+        result.extend(self.neighbours(pos));
+        result
+    }
+}
+
+/// The classic grid, each cell counting the mines of its `neighbourhood`, the 8 cells around by default.
+#[derive(Default)]
+pub struct Square {
+    pub neighbourhood: Neighbourhood,
+}
+
+impl Topology for Square {
+    fn neighbours(&self, pos: WorldPos) -> Vec<WorldPos> {
+        self.neighbourhood.offsets().iter().map(|o| pos + o).collect()
+    }
+
+    fn cell_at(&self, x: f32, y: f32) -> WorldPos {
+        WorldPos::containing(x, y)
+    }
+
+    fn polygon(&self, pos: WorldPos) -> Vec<(f32, f32)> {
+        let (x, y) = (pos.x as f32, pos.y as f32);
+        vec![(x, y), (x + 1.0, y), (x + 1.0, y + 1.0), (x, y + 1.0)]
+    }
+}
+
+/// Distance between rows of hexagons, and height of a triangle of side 1: √3 / 2.
+const ROW: f32 = 0.866_025_4;
+
+/// Hexagons 1 wide with a corner up, each touching 6 others. Rows are offset by half a cell in turn,
+/// odd rows sticking out to the right.
+pub struct Hex;
+
+impl Hex {
+    /// Distance from the middle of a hexagon to its corners: 1 / √3.
+    const RADIUS: f32 = 0.577_350_3;
+}
+
+impl Topology for Hex {
+    fn neighbours(&self, pos: WorldPos) -> Vec<WorldPos> {
+        // Rows above and below touch the cells at x - 1 and x in even rows, x and x + 1 in odd ones
+        let s = pos.y.rem_euclid(2);
+        [(-1, 0), (1, 0), (s - 1, -1), (s, -1), (s - 1, 1), (s, 1)].iter().map(|o| pos + o).collect()
+    }
+
+    /// The cell whose middle is the closest, which is the hexagon the point is in.
+    fn cell_at(&self, x: f32, y: f32) -> WorldPos {
+        let guess = WorldPos::containing(x, y / ROW);
+        let distance = |pos: WorldPos| {
+            let (cx, cy) = self.center(pos);
+            (cx - x).powi(2) + (cy - y).powi(2)
+        };
+        NEIGHBOURS.iter()
+            .map(|o| guess + o)
+            .chain([guess])
+            .min_by(|a, b| distance(*a).total_cmp(&distance(*b)))
+            .unwrap()
+    }
+
+    fn polygon(&self, pos: WorldPos) -> Vec<(f32, f32)> {
+        let (x, y) = self.center(pos);
+        (0..6)
+            .map(|i| {
+                let angle = (i as f32 * 60.0 - 90.0).to_radians();
+                (x + Hex::RADIUS * angle.cos(), y + Hex::RADIUS * angle.sin())
+            })
+            .collect()
+    }
+
+    fn center(&self, pos: WorldPos) -> (f32, f32) {
+        (pos.x as f32 + 0.5 * (1 + pos.y.rem_euclid(2)) as f32, pos.y as f32 * ROW + Hex::RADIUS)
+    }
+}
+
+/// Triangles of side 1 pointing up and down in turn, each touching 12 others by an edge or a corner.
+/// Cell (x, y) starts at x / 2 in row y, and points up when x + y is even.
+pub struct Triangle;
+
+impl Triangle {
+    fn points_up(pos: WorldPos) -> bool {
+        (pos.x + pos.y).rem_euclid(2) == 0
+    }
+}
+
+impl Topology for Triangle {
+    fn neighbours(&self, pos: WorldPos) -> Vec<WorldPos> {
+        // 3 cells in the row at the tip, 5 in the row along the base and 4 in the same row
+        let tip = if Triangle::points_up(pos) { -1 } else { 1 };
+        let mut result = Vec::with_capacity(12);
+        result.extend((-1..=1).map(|x| pos + (x, tip)));
+        result.extend((-2..=2).map(|x| pos + (x, -tip)));
+        result.extend([-2, -1, 1, 2].map(|x| pos + (x, 0)));
+        result
+    }
+
+    fn cell_at(&self, x: f32, y: f32) -> WorldPos {
+        let row = (y / ROW).floor() as i32;
+        let last = (2.0 * x).floor() as i32;
+        let candidates = (last - 2..=last).map(|x| WorldPos::new(x, row));
+        candidates.clone()
+            .find(|pos| {
+                let corners = self.polygon(*pos);
+                let side = |a: (f32, f32), b: (f32, f32)| (b.0 - a.0) * (y - a.1) - (b.1 - a.1) * (x - a.0);
+                let sides = [side(corners[0], corners[1]), side(corners[1], corners[2]), side(corners[2], corners[0])];
+                sides.iter().all(|s| *s >= 0.0) || sides.iter().all(|s| *s <= 0.0)
+            })
+            .unwrap_or(WorldPos::new(last - 1, row))
+    }
+
+    fn polygon(&self, pos: WorldPos) -> Vec<(f32, f32)> {
+        let (x, top, bottom) = (pos.x as f32 / 2.0, pos.y as f32 * ROW, (pos.y + 1) as f32 * ROW);
+        if Triangle::points_up(pos) {
+            vec![(x + 0.5, top), (x + 1.0, bottom), (x, bottom)]
+        } else {
+            vec![(x, top), (x + 1.0, top), (x + 0.5, bottom)]
+        }
+    }
+}
+
+/// Squares on a finite board whose sides wrap around: the first column touches the last one,
+/// and the first row the last one.
+pub struct Torus {
+    pub width: u32,
+    pub height: u32,
+    pub neighbourhood: Neighbourhood,
+}
+
+impl Topology for Torus {
+    fn neighbours(&self, pos: WorldPos) -> Vec<WorldPos> {
+        let offsets = self.neighbourhood.offsets();
+        let mut result: Vec<WorldPos> = Vec::with_capacity(offsets.len());
+        for o in offsets {
+            let p = pos + o;
+            let wrapped = WorldPos::new(p.x.rem_euclid(self.width as i32), p.y.rem_euclid(self.height as i32));
+            // Boards narrower than the neighbourhood reach some cells several ways
+            if wrapped != pos && !result.contains(&wrapped) {
+                result.push(wrapped);
+            }
+        }
+        result
+    }
+
+    fn cell_at(&self, x: f32, y: f32) -> WorldPos {
+        WorldPos::containing(x, y)
+    }
+
+    fn polygon(&self, pos: WorldPos) -> Vec<(f32, f32)> {
+        Square::default().polygon(pos)
+    }
+}
+
+/// Cubes of a finite volume `width` x `height` x `depth`, each touching the 26 cubes around it.
+/// Layer z of the volume is rows `z * height` to `(z + 1) * height - 1` of a board `width` x `height * depth`.
+/// Flat, the layers are drawn side by side, one cell apart.
+pub struct Voxel {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+}
+
+impl Voxel {
+    /// The volume of a board `size` whose rows are split into `layers`, which `Config::check` makes sure they can be evenly.
+    pub fn of(size: BoardSize, layers: u32) -> Voxel {
+        Voxel { width: size.width, height: size.height / layers, depth: layers }
+    }
+
+    /// Coordinates in the volume of the cell at `pos`.
+    pub fn voxel(&self, pos: WorldPos) -> [i32; 3] {
+        let h = self.height as i32;
+        [pos.x, pos.y.rem_euclid(h), pos.y.div_euclid(h)]
+    }
+
+    /// Cell at `[x, y, z]` in the volume, `None` outside of it.
+    pub fn cell(&self, [x, y, z]: [i32; 3]) -> Option<WorldPos> {
+        let inside = (0..self.width as i32).contains(&x) && (0..self.height as i32).contains(&y) && (0..self.depth as i32).contains(&z);
+        inside.then(|| WorldPos::new(x, y + z * self.height as i32))
+    }
+}
+
+impl Topology for Voxel {
+    fn neighbours(&self, pos: WorldPos) -> Vec<WorldPos> {
+        let [x, y, z] = self.voxel(pos);
+        let mut result = Vec::with_capacity(26);
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if (dx, dy, dz) != (0, 0, 0) {
+                        result.extend(self.cell([x + dx, y + dy, z + dz]));
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Points between the layers, or above and below them, are off the board.
+    fn cell_at(&self, x: f32, y: f32) -> WorldPos {
+        let stride = (self.width + 1) as f32;
+        let z = (x / stride).floor();
+        let cell = [(x - z * stride).floor() as i32, y.floor() as i32, z as i32];
+        self.cell(cell).unwrap_or(WorldPos::new(-1, -1))
+    }
+
+    fn polygon(&self, pos: WorldPos) -> Vec<(f32, f32)> {
+        let [x, y, z] = self.voxel(pos);
+        Square::default().polygon(WorldPos::new(x + z * (self.width as i32 + 1), y))
+    }
+}
+
+/// Which topology a game is played on, as stored in configs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TopologyKind {
+    #[default]
+    Square,
+    Hex,
+    Triangle,
+    Torus,
+    Voxel,
+}
+
+impl TopologyKind {
+    pub const ALL: [TopologyKind; 5] = [
+        TopologyKind::Square, TopologyKind::Hex, TopologyKind::Triangle, TopologyKind::Torus, TopologyKind::Voxel,
+    ];
+
+    /// The topology of a game on `board`. A torus wraps around a finite board, and a voxel volume
+    /// splits its rows into `layers`. Neither is played in the infinite world, which `Config::check` rejects,
+    /// so both are left the square grid there.
+    /// Squares count the mines of `neighbourhood`, hexagons and triangles always count the cells they touch.
+    pub fn build(self, board: Option<BoardSize>, neighbourhood: &Neighbourhood, layers: u32) -> Box<dyn Topology> {
+        let neighbourhood = neighbourhood.clone();
+        match (self, board) {
+            (TopologyKind::Square, _) | (TopologyKind::Torus | TopologyKind::Voxel, None) => Box::new(Square { neighbourhood }),
+            (TopologyKind::Hex, _) => Box::new(Hex),
+            (TopologyKind::Triangle, _) => Box::new(Triangle),
+            (TopologyKind::Torus, Some(size)) => Box::new(Torus { width: size.width, height: size.height, neighbourhood }),
+            (TopologyKind::Voxel, Some(size)) => Box::new(Voxel::of(size, layers)),
+        }
+    }
+
+    /// Whether cells are squares, whose neighbourhood can be changed.
+    pub fn is_square(self) -> bool {
+        matches!(self, TopologyKind::Square | TopologyKind::Torus)
+    }
+}
+
+impl fmt::Display for TopologyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TopologyKind::Square => "square",
+            TopologyKind::Hex => "hex",
+            TopologyKind::Triangle => "triangle",
+            TopologyKind::Torus => "torus",
+            TopologyKind::Voxel => "voxel",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Parses `square`, `hex`, `triangle`, `torus` or `voxel`.
+impl FromStr for TopologyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TopologyKind, String> {
+        TopologyKind::ALL.into_iter()
+            .find(|t| t.to_string() == s)
+            .ok_or_else(|| format!("unknown topology '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn neighbours_touch_both_ways() {
+        let torus = Torus { width: 5, height: 4, neighbourhood: Neighbourhood::Classic };
+        let topologies: [&dyn Topology; 4] = [&Square::default(), &Hex, &Triangle, &torus];
+        for (topology, count) in topologies.into_iter().zip([8, 6, 12, 8]) {
+            for pos in [WorldPos::new(0, 0), WorldPos::new(3, 1), WorldPos::new(2, 2), WorldPos::new(4, 3)] {
+                let neighbours = topology.neighbours(pos);
+                assert_eq!(neighbours.len(), count);
+                for n in neighbours {
+                    assert!(n != pos);
+                    assert!(topology.neighbours(n).contains(&pos), "{:?} next to {:?} but not the other way", n, pos);
+                }
+            }
+        }
+        assert!(torus.neighbours(WorldPos::new(0, 0)).contains(&WorldPos::new(4, 3)));
+        let narrow = Torus { width: 2, height: 1, neighbourhood: Neighbourhood::Classic };
+        assert_eq!(narrow.neighbours(WorldPos::new(0, 0)), vec![WorldPos::new(1, 0)]);
+    }
+
+    #[test]
+    fn voxels_touch_in_3d() {
+        let volume = Voxel { width: 4, height: 3, depth: 5 };
+        let middle = volume.cell([1, 1, 2]).unwrap();
+        assert_eq!(middle, WorldPos::new(1, 7));
+        assert_eq!(volume.voxel(middle), [1, 1, 2]);
+        assert_eq!(volume.neighbours(middle).len(), 26);
+        assert_eq!(volume.neighbours(WorldPos::new(0, 0)).len(), 7);
+        // A row at the bottom of a layer doesn't touch the top of the next one, only the cells above and below
+        let bottom = volume.cell([1, 2, 0]).unwrap();
+        assert!(!volume.neighbours(bottom).contains(&volume.cell([1, 0, 0]).unwrap()));
+        assert!(volume.neighbours(bottom).contains(&volume.cell([1, 2, 1]).unwrap()));
+        for pos in volume.neighbours(middle) {
+            assert!(volume.neighbours(pos).contains(&middle));
+        }
+
+        // Layers side by side
+        let (x, y) = volume.center(volume.cell([2, 1, 3]).unwrap());
+        assert_eq!((x, y), (17.5, 1.5));
+        assert_eq!(volume.cell_at(x, y), volume.cell([2, 1, 3]).unwrap());
+        assert_eq!(volume.cell_at(4.5, 1.5), WorldPos::new(-1, -1));
+        assert_eq!(volume.cell_at(1.5, 3.5), WorldPos::new(-1, -1));
+    }
+
+    #[test]
+    fn cells_are_picked_from_their_middle() {
+        let topologies: [&dyn Topology; 3] = [&Square::default(), &Hex, &Triangle];
+        for topology in topologies {
+            for y in -5..5 {
+                for x in -5..5 {
+                    let pos = WorldPos::new(x, y);
+                    let (cx, cy) = topology.center(pos);
+                    assert_eq!(topology.cell_at(cx, cy), pos);
+                    // Close to a corner, still inside
+                    let (px, py) = topology.polygon(pos)[0];
+                    assert_eq!(topology.cell_at(px + (cx - px) * 0.1, py + (cy - py) * 0.1), pos);
+                }
+            }
+        }
+        assert_eq!(Hex.cell_at(0.1, 0.9), WorldPos::new(0, 0));
+        assert_eq!(Hex.cell_at(1.0, 1.4), WorldPos::new(0, 1));
+        assert_eq!(Triangle.cell_at(0.1, 0.8), WorldPos::new(0, 0));
+        assert_eq!(Triangle.cell_at(0.1, 0.1), WorldPos::new(-1, 0));
+        assert_eq!("hex".parse(), Ok(TopologyKind::Hex));
+        assert!("cube".parse::<TopologyKind>().is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::*;
+
+/// How many mines each mined cell holds. A mined cell holds 2 mines with chance `double`,
+/// 3 with chance `triple`, a negative mine taking 1 off the numbers around with chance `negative`,
+/// and a single mine otherwise. All of them at 0 is the classic game.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MineWeights {
+    pub double: f32,
+    pub triple: f32,
+    pub negative: f32,
+}
+
+impl MineWeights {
+    /// Weights of a mine, in the order flags go through them.
+    const ALL: [i32; 4] = [1, 2, 3, -1];
+
+    pub fn check(&self) -> Result<(), String> {
+        let chances = [self.double, self.triple, self.negative];
+        if chances.iter().any(|c| !(0.0..=1.0).contains(c)) || chances.iter().sum::<f32>() > 1.0 {
+            return Err("mine weight chances must be between 0 and 1, and add up to 1 at most".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn is_classic(&self) -> bool {
+        *self == MineWeights::default()
+    }
+
+    /// Chance of a mined cell to hold mines of `weight`.
+    pub fn chance(&self, weight: i32) -> f32 {
+        match weight {
+            1 => 1.0 - self.double - self.triple - self.negative,
+            2 => self.double,
+            3 => self.triple,
+            -1 => self.negative,
+            _ => 0.0,
+        }
+    }
+
+    /// Weights a mine can have, in the order flags go through them.
+    pub fn values(&self) -> Vec<i32> {
+        MineWeights::ALL.into_iter().filter(|w| self.chance(*w) > 0.0).collect()
+    }
+
+    /// Weight of the mine at `pos`, if there is one. Drawn from the seed and the position only,
+    /// so a mine weighs the same seen from any chunk, whether its own is generated or not.
+    pub fn weight(&self, seed: u64, pos: WorldPos) -> i32 {
+        if self.is_classic() {
+            return 1;
+        }
+        let mut rng = Rng::for_cell(seed ^ 0x3EA7_11E5_F0BA_11A5, pos);
+        let mut unit = (rng.next_u64() >> 40) as f32 / (1 << 24) as f32;
+        for w in [2, 3, -1] {
+            if unit < self.chance(w) {
+                return w;
+            }
+            unit -= self.chance(w);
+        }
+        1
+    }
+
+    /// Flag a cell flagged with `flag` gets when flagged again, 0 being no flag.
+    pub fn next_flag(&self, flag: i32) -> i32 {
+        let values = self.values();
+        match values.iter().position(|w| *w == flag) {
+            Some(i) => values.get(i + 1).copied().unwrap_or(0),
+            None => values[0],
+        }
+    }
+
+    /// Number of times an unflagged cell is flagged to mark a mine of `weight`.
+    pub fn toggles(&self, weight: i32) -> usize {
+        self.values().iter().position(|w| *w == weight).map_or(1, |i| i + 1)
+    }
+}
+
+/// Writes `DOUBLE/TRIPLE/NEGATIVE`, as parsed by `from_str`.
+impl fmt::Display for MineWeights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.double, self.triple, self.negative)
+    }
+}
+
+/// Parses the chances of a mined cell to hold 2 mines, 3 mines, or a negative mine:
+/// `DOUBLE/TRIPLE/NEGATIVE`, e.g. `0.2/0.1/0.1`.
+impl FromStr for MineWeights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MineWeights, String> {
+        let invalid = || format!("invalid mine weights '{}', expected DOUBLE/TRIPLE/NEGATIVE", s);
+        let chances: Vec<f32> = s.split('/').map(|c| c.parse().map_err(|_| invalid())).collect::<Result<_, _>>()?;
+        let [double, triple, negative] = chances[..] else {
+            return Err(invalid());
+        };
+        let result = MineWeights { double, triple, negative };
+        result.check()?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn weights_are_drawn_per_cell() {
+        let weights: MineWeights = "0.25/0.25/0.25".parse().unwrap();
+        assert_eq!(weights.to_string().parse(), Ok(weights));
+        let drawn: Vec<i32> = (0..1000).map(|x| weights.weight(9, WorldPos::new(x, -x))).collect();
+        for w in [1, 2, 3, -1] {
+            let n = drawn.iter().filter(|d| **d == w).count();
+            assert!((180..320).contains(&n), "{} mines of weight {}", n, w);
+        }
+        assert_eq!(weights.weight(9, WorldPos::new(5, -5)), drawn[5]);
+        assert_eq!(MineWeights::default().weight(9, WorldPos::new(5, -5)), 1);
+
+        // Flags go through the weights that can happen only
+        let heavy = MineWeights { double: 1.0, triple: 0.0, negative: 0.0 };
+        assert_eq!(heavy.values(), vec![2]);
+        assert_eq!((heavy.next_flag(0), heavy.next_flag(2)), (2, 0));
+        assert_eq!(weights.next_flag(3), -1);
+        assert_eq!(weights.toggles(-1), 4);
+        assert!("0.5/0.5/0.5".parse::<MineWeights>().is_err());
+        assert!("0.5/0.5".parse::<MineWeights>().is_err());
+    }
+}
@@ -42,6 +42,25 @@ impl AABB {
             false
         }
     }
+
+    /// Distance along the ray from `origin` towards `direction` at which it enters the box,
+    /// 0 if it starts inside, `None` if it misses it.
+    pub fn ray_hit(&self, origin: Point3, direction: Vec3) -> Option<f32> {
+        let (mut near, mut far) = (0.0f32, f32::INFINITY);
+        for axis in 0..3 {
+            let (min, max, o, d) = (self.min[axis], self.max[axis], origin[axis], direction[axis]);
+            if d == 0.0 {
+                if o < min || o > max {
+                    return None;
+                }
+                continue;
+            }
+            let (t0, t1) = ((min - o) / d, (max - o) / d);
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        (near <= far).then_some(near)
+    }
 }
 
 #[cfg(test)]
@@ -88,4 +107,15 @@ mod tests {
             z: 2.0
         }));
     }
+
+    #[test]
+    fn ray_hit() {
+        let aabb = AABB::from_size(Vec3::new(0.0, 0.0, 0.0), 2.0);
+        assert_eq!(aabb.ray_hit(Point3::new(-5.0, 0.0, 0.0), RIGHT), Some(4.0));
+        assert_eq!(aabb.ray_hit(Point3::new(-5.0, 0.0, 0.0), LEFT), None);
+        assert_eq!(aabb.ray_hit(Point3::new(-5.0, 2.0, 0.0), RIGHT), None);
+        assert_eq!(aabb.ray_hit(Point3::new(0.5, 0.0, 0.0), UP), Some(0.0));
+        let hit = aabb.ray_hit(Point3::new(-5.0, -5.0, 0.0), Vec3::new(1.0, 1.0, 0.0)).unwrap();
+        assert!((hit - 4.0).abs() < 1e-5);
+    }
 }
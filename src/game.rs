@@ -1,18 +1,63 @@
+use sdl2::{event::Event, keyboard::{Mod, Scancode}, mouse::MouseButton};
 
-use sdl2::{event::Event, keyboard::Scancode, mouse::MouseButton};
+use std::collections::HashMap;
+use std::path::Path;
 
-use crate::{math::*, renderer::*, input::*, sprite_sheet::SpriteSheet, resources::*};
+use minesweeper_core::*;
 
-enum State {
-    Playing,
-    Lost,
-}
+use crate::{math::*, renderer::*, input::*, sprite_sheet::SpriteSheet, resources::*, text::*, volume::VolumeView, WINDOW_SIZE};
 
 pub struct GameState {
     pub delta_time: f32,
-    grid: Grid,
-    state: State,
+    game: Game,
     camera: Camera,
+    left_down: bool,
+    right_down: bool,
+    /// Where the middle button was pressed, to tell a click from a drag.
+    middle_down_at: Option<(i32, i32)>,
+    cursor: Vec2,
+    /// Mine probabilities drawn over the board, when turned on.
+    probabilities: Option<HashMap<WorldPos, f32>>,
+    /// The board changed since the probabilities were computed.
+    probabilities_dirty: bool,
+    /// Recording of the current game, `None` for a game resumed from a save as replays start from scratch.
+    replay: Option<Replay>,
+    /// Seconds since the start of the current game.
+    elapsed: f32,
+    /// Camera as of the last recorded camera move.
+    recorded_camera: (i32, i32, i32),
+    /// A replay being watched instead of a game being played.
+    playback: Option<Playback>,
+    /// Statistics of the games played, saved in `PROFILE_FILE`.
+    profile: Profile,
+    /// The current game is already in the statistics.
+    counted: bool,
+    /// Cells the view moved over during the current game.
+    distance: f32,
+    show_stats: bool,
+    /// 3D view of a voxel board.
+    volume: Option<VolumeView>,
+    /// Connection to the server of a cooperative game, which plays the actions.
+    remote: Option<Client>,
+    /// State of the game as last told to the player.
+    shown_state: State,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Speed {
+    Normal,
+    Fast,
+    /// One action per key press.
+    Stepped,
+}
+
+pub struct Playback {
+    replay: Replay,
+    /// Index of the next action to play.
+    next: usize,
+    /// Milliseconds of the replay played so far.
+    clock: f32,
+    speed: Speed,
 }
 
 struct Camera {
@@ -25,8 +70,25 @@ impl Camera {
         self.zoom as f32
     }
 
-    pub fn screen_to_world(&self, pos: Vec2) -> Vec2i {
-        return ((self.position.vec2() + pos) / self.cell_size()).vec2i();
+    /// Cell under the point `pos` of the screen.
+    pub fn screen_to_world(&self, pos: Vec2, topology: &dyn Topology) -> WorldPos {
+        let p = (self.position.vec2() + pos) / self.cell_size();
+        topology.cell_at(p.x, p.y)
+    }
+
+    /// Where a point of the world, in cells, is on screen.
+    pub fn world_to_screen(&self, (x, y): (f32, f32)) -> Vec2 {
+        Vec2::new(x, y) * self.cell_size() - self.position.vec2()
+    }
+
+    /// Chunks to keep loaded around the screen: those under its corners, and one more all around.
+    pub fn visible_chunks(&self, topology: &dyn Topology) -> ChunkRect {
+        let (w, h) = (WINDOW_SIZE.0 as f32, WINDOW_SIZE.1 as f32);
+        let corners = [(0.0, 0.0), (w, 0.0), (0.0, h), (w, h)]
+            .map(|(x, y)| self.screen_to_world(Vec2::new(x, y), topology).chunk());
+        let min = ChunkPos::new(corners.iter().map(|c| c.x).min().unwrap(), corners.iter().map(|c| c.y).min().unwrap());
+        let max = ChunkPos::new(corners.iter().map(|c| c.x).max().unwrap(), corners.iter().map(|c| c.y).max().unwrap());
+        ChunkRect::new(min + (-1, -1), max + (1, 1))
     }
 }
 
@@ -34,58 +96,428 @@ const SPRITE_HIDDEN: (i32, i32) = (0, 2);
 const SPRITE_FLAG: (i32, i32) = (1, 2);
 const SPRITE_MINE: (i32, i32) = (2, 2);
 const SPRITE_0: (i32, i32) = (3, 2);
+const SPRITE_MINE_2: (i32, i32) = (0, 3);
+const SPRITE_MINE_3: (i32, i32) = (1, 3);
+const SPRITE_MINE_NEGATIVE: (i32, i32) = (2, 3);
+const SPRITE_FLAG_2: (i32, i32) = (3, 3);
+const SPRITE_FLAG_3: (i32, i32) = (0, 4);
+const SPRITE_FLAG_NEGATIVE: (i32, i32) = (1, 4);
+
+/// Sprite of a mine holding `weight` mines.
+fn mine_sprite(weight: i32) -> (i32, i32) {
+    match weight {
+        2 => SPRITE_MINE_2,
+        3 => SPRITE_MINE_3,
+        -1 => SPRITE_MINE_NEGATIVE,
+        _ => SPRITE_MINE,
+    }
+}
 
-const DENSITY: f32 = 0.1;
+/// Sprite of a flag marking `flag` mines.
+fn flag_sprite(flag: i32) -> (i32, i32) {
+    match flag {
+        2 => SPRITE_FLAG_2,
+        3 => SPRITE_FLAG_3,
+        -1 => SPRITE_FLAG_NEGATIVE,
+        _ => SPRITE_FLAG,
+    }
+}
+
+const CELL_SIZE: i32 = 16;
+/// Cells that aren't squares are drawn this much smaller, leaving a gap between them.
+const CELL_INSET: f32 = 0.9;
+/// Side of the flag, mine and number drawn in a cell that isn't a square, in cells.
+const ICON_SIZE: f32 = 0.55;
+const SAVE_DIR: &str = "./saves";
+const REPLAY_DIR: &str = "./replays";
+/// Where the game left on quit is saved.
+pub const SAVE_FILE: &str = "./saves/game.sav";
+const PROFILE_FILE: &str = "./saves/profile.stats";
+/// Where statistics are exported, as `.json` and `.csv`.
+const STATS_EXPORT: &str = "./stats";
+/// How many times faster than recorded a replay plays at fast speed.
+const FAST_SPEED: f32 = 8.0;
+/// Distance in pixels the mouse can move during a middle click before it counts as a drag.
+const CLICK_SLOP: i32 = 4;
 
 impl Default for GameState {
     fn default() -> Self {
-        GameState {
-            delta_time : 0.0,
-            grid: Grid::new(),
-            state: State::Playing,
-            camera: Camera { position: Vec2i::new(0, 0), zoom: CELL_SIZE },
-        }
+        GameState::new()
     }
 }
 
 impl GameState {
     pub fn new() -> GameState {
+        GameState::with_config(rand::random(), Config::default())
+    }
+
+    pub fn with_config(seed: u64, config: Config) -> GameState {
+        let mut result = GameState::with_game(Game::new(Grid::new(seed), config));
+        result.restart(seed);
+        result
+    }
+
+    /// Shows `game` as it is, with nothing recorded.
+    fn with_game(game: Game) -> GameState {
         GameState {
-            grid: Grid::new(),
-            ..Default::default()
+            delta_time : 0.0,
+            game,
+            camera: Camera { position: Vec2i::new(0, 0), zoom: CELL_SIZE },
+            left_down: false,
+            right_down: false,
+            middle_down_at: None,
+            cursor: Vec2::new(0.0, 0.0),
+            probabilities: None,
+            probabilities_dirty: false,
+            replay: None,
+            elapsed: 0.0,
+            recorded_camera: (0, 0, 0),
+            playback: None,
+            profile: Profile::load(Path::new(PROFILE_FILE)).unwrap_or_else(|e| {
+                println!("Unable to load statistics, starting afresh: {}", e);
+                Profile::default()
+            }),
+            counted: false,
+            distance: 0.0,
+            show_stats: false,
+            volume: None,
+            remote: None,
+            shown_state: State::Playing,
+        }
+    }
+
+    /// Watches `replay` from the start.
+    pub fn with_replay(replay: Replay, speed: Speed) -> GameState {
+        let mut result = GameState::with_game(replay.start());
+        result.show_game();
+        result.playback = Some(Playback { replay, next: 0, clock: 0.0, speed });
+        result
+    }
+
+    /// Plays the cooperative game `game` of the server `client` is connected to.
+    /// Its world stays on the server, nothing is saved or recorded.
+    pub fn with_client(client: Client, game: Game) -> GameState {
+        GameState { remote: Some(client), ..GameState::with_game(game) }
+    }
+
+    /// Carries on with a saved game.
+    pub fn with_save(saved: &SavedGame) -> GameState {
+        let mut result = GameState::with_game(saved.restore(Path::new(SAVE_DIR)));
+        result.show_game();
+        result.elapsed = saved.elapsed as f32 / 1000.0;
+        let (x, y, zoom) = saved.camera;
+        result.camera = Camera { position: Vec2i::new(x, y), zoom };
+        result.recorded_camera = saved.camera;
+        result.distance = saved.distance;
+        println!("Resumed game {} after {:.0} s", saved.seed, result.elapsed);
+        result
+    }
+
+    /// Starts a new game with the same config.
+    fn restart(&mut self, seed: u64) {
+        self.leave_game();
+        self.end_recording();
+        self.playback = None;
+        println!("World seed: {}", seed);
+        let config = self.game.config.clone();
+        let grid = match config.board {
+            Some(size) => Grid::fixed(seed, size),
+            None => Grid::persistent(seed, Path::new(SAVE_DIR)),
+        };
+        self.replay = Some(Replay::new(seed, config.clone()));
+        self.elapsed = 0.0;
+        self.recorded_camera = (0, 0, 0);
+        self.counted = false;
+        self.distance = 0.0;
+        self.game = Game::new(grid, config);
+        self.show_game();
+    }
+
+    /// Sets the view up for the game just started or loaded, its board centred on screen.
+    fn show_game(&mut self) {
+        self.probabilities_dirty = true;
+        let config = &self.game.config;
+        self.volume = match (config.topology, config.board) {
+            (TopologyKind::Voxel, Some(size)) => Some(VolumeView::new(Voxel::of(size, config.layers))),
+            _ => None,
+        };
+        self.camera.position = match self.game.config.board {
+            // Center the board on screen, its far corner being in one of the last two cells of the last column
+            Some(size) => {
+                let topology = self.game.grid.topology();
+                let (w, h) = [size.height as i32 - 1, size.height as i32 - 2].iter()
+                    .flat_map(|y| topology.polygon(WorldPos::new(size.width as i32 - 1, *y)))
+                    .fold((0.0f32, 0.0f32), |(w, h), (x, y)| (w.max(x), h.max(y)));
+                Vec2i::new(
+                    ((w * self.camera.zoom as f32) as i32 - WINDOW_SIZE.0 as i32) / 2,
+                    ((h * self.camera.zoom as f32) as i32 - WINDOW_SIZE.1 as i32) / 2,
+                )
+            },
+            None => Vec2i::new(0, 0),
+        };
+    }
+
+    /// Saves the world and the recording of the game being left.
+    fn end_recording(&mut self) {
+        if self.playback.is_some() {
+            return;
+        }
+        if let Err(e) = self.game.grid.save() {
+            println!("Unable to save the world: {}", e);
+        }
+        if self.replay.as_ref().is_some_and(|r| !r.actions.is_empty()) {
+            self.save_replay("last");
+        }
+    }
+
+    /// Leaving a game after playing counts as a loss, in the mode it was played.
+    fn leave_game(&mut self) {
+        if self.game.clicks() > 0 {
+            self.count_game();
+        }
+    }
+
+    /// Adds the current game to the statistics, once.
+    fn count_game(&mut self) {
+        if self.counted || self.playback.is_some() || self.remote.is_some() {
+            return;
+        }
+        self.counted = true;
+        let date = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let result = GameResult::new(&self.game, (self.elapsed * 1000.0) as u64, self.distance, date);
+        let mode = self.game.config.mode();
+        self.profile.record(&mode, &result);
+        let stats = &self.profile.modes[&mode];
+        if result.won && stats.best_time() == Some(result.time) {
+            println!("New best time in {}: {:.2} s", mode, result.time as f32 / 1000.0);
+        }
+        if result.won {
+            println!(
+                "3BV {} in {} clicks: {:.2} 3BV/s, {:.0}% efficiency",
+                result.bbbv, result.clicks, result.bbbv_per_second(), result.efficiency() * 100.0,
+            );
         }
+        if let Err(e) = self.profile.save(Path::new(PROFILE_FILE)) {
+            println!("Unable to save statistics: {}", e);
+        }
+    }
+
+    /// Prints how hard the board is, or the part of the infinite world on screen.
+    fn print_metrics(&mut self) {
+        let metrics = match Metrics::of_board(&self.game.grid) {
+            Some(metrics) => metrics,
+            None => {
+                let topology = self.game.grid.topology();
+                let min = self.camera.screen_to_world(Vec2::new(0.0, 0.0), topology);
+                let max = self.camera.screen_to_world(Vec2::new(WINDOW_SIZE.0 as f32 - 1.0, WINDOW_SIZE.1 as f32 - 1.0), topology);
+                Metrics::of_region(&mut self.game.grid, min, max)
+            },
+        };
+        println!(
+            "{} cells, {} mines: 3BV {}, {} openings (largest {}), {} islands",
+            metrics.cells, metrics.mines, metrics.bbbv, metrics.openings.len(),
+            metrics.openings.first().copied().unwrap_or(0), metrics.islands,
+        );
+    }
+
+    /// Writes the statistics as JSON and CSV.
+    fn export_stats(&self) {
+        for (extension, text) in [("json", self.profile.to_json()), ("csv", self.profile.to_csv())] {
+            let path = Path::new(STATS_EXPORT).with_extension(extension);
+            match std::fs::write(&path, text) {
+                Ok(()) => println!("Statistics exported to {}", path.display()),
+                Err(e) => println!("Unable to export statistics to {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    fn save_replay(&self, name: &str) {
+        let Some(replay) = &self.replay else {
+            println!("No replay of a resumed game");
+            return;
+        };
+        let path = Path::new(REPLAY_DIR).join(format!("{}.replay", name));
+        match replay.save(&path) {
+            Ok(()) => println!("Replay saved to {}", path.display()),
+            Err(e) => println!("Unable to save replay {}: {}", path.display(), e),
+        }
+    }
+
+    /// Plays `action` and records it, or has the server play it.
+    fn act(&mut self, action: Action) {
+        if let Some(client) = &mut self.remote {
+            client.play(action);
+            return;
+        }
+        let (lives, started) = (self.game.lives(), self.game.grid.safe_zone().is_some());
+        let revealed = action.apply(&mut self.game);
+        self.record(action);
+        self.tell_mines(revealed.mines, lives);
+        if !started && self.game.grid.unsolved_cells() > 0 {
+            println!("No board without guess found, {} cells need one", self.game.grid.unsolved_cells());
+        }
+    }
+
+    fn record(&mut self, action: Action) {
+        if let Some(replay) = &mut self.replay {
+            replay.push((self.elapsed * 1000.0) as u32, action);
+        }
+    }
+
+    /// The 3D view, when a voxel board is shown in 3D.
+    fn volume(&self) -> Option<&VolumeView> {
+        self.volume.as_ref().filter(|v| v.shown)
+    }
+
+    /// Cell under the point `(x, y)` of the screen, `None` when no cube of the 3D view is there.
+    fn cell_under(&self, x: i32, y: i32) -> Option<WorldPos> {
+        let cursor = Vec2::new(x as f32, y as f32);
+        match self.volume() {
+            Some(volume) => volume.pick(&self.game.grid, cursor),
+            None => Some(self.camera.screen_to_world(cursor, self.game.grid.topology())),
+        }
+    }
+
+    fn set_board(&mut self, board: Option<BoardSize>) {
+        self.leave_game();
+        self.game.config.board = board;
+        self.restart(rand::random());
     }
 
     pub fn event(&mut self, event: Event) {
+        if let Event::MouseButtonDown { .. } | Event::KeyDown { .. } = event {
+            self.probabilities_dirty = true;
+        }
+        if let Some(playback) = &mut self.playback {
+            match event {
+                Event::KeyDown { scancode: Some(Scancode::Space), .. } => {
+                    playback.speed = Speed::Stepped;
+                    self.step_replay();
+                    return;
+                },
+                Event::KeyDown { scancode: Some(Scancode::Return), .. } => {
+                    playback.speed = if playback.speed == Speed::Normal { Speed::Fast } else { Speed::Normal };
+                    println!("Playback speed: {:?}", playback.speed);
+                    return;
+                },
+                // Watching, not playing
+                Event::MouseButtonDown { .. } | Event::MouseButtonUp { .. } | Event::MouseWheel { .. }
+                    | Event::KeyDown { scancode: Some(Scancode::F | Scancode::M | Scancode::Z | Scancode::Y), .. } => return,
+                _ => {},
+            }
+        }
+        if let Some(client) = &mut self.remote {
+            match event {
+                // The server starts a new world for everyone, once the game is over
+                Event::KeyDown { scancode: Some(Scancode::R), .. } => {
+                    if self.game.state() != State::Playing {
+                        client.restart();
+                    }
+                    return;
+                },
+                // The game is the server's, the player only acts on cells
+                Event::KeyDown { scancode: Some(
+                    Scancode::G | Scancode::M | Scancode::I | Scancode::F5 | Scancode::Z | Scancode::Y
+                    | Scancode::Num0 | Scancode::Num1 | Scancode::Num2 | Scancode::Num3
+                ), .. } => return,
+                _ => {},
+            }
+        }
         match event {
             Event::MouseButtonDown { mouse_btn, x, y, .. } => {
-                if let State::Playing = self.state {
-                    match mouse_btn {
-                        sdl2::mouse::MouseButton::Left => self.on_left_click(x, y),
-                        sdl2::mouse::MouseButton::Right => self.on_right_click(x, y),
-                        _ => {}
-                    }
+                match mouse_btn {
+                    sdl2::mouse::MouseButton::Left => {
+                        self.left_down = true;
+                        self.on_left_click(x, y);
+                    },
+                    sdl2::mouse::MouseButton::Right => {
+                        self.right_down = true;
+                        self.on_right_click(x, y);
+                    },
+                    sdl2::mouse::MouseButton::Middle => self.middle_down_at = Some((x, y)),
+                    _ => {}
+                }
+            },
+            Event::MouseButtonUp { mouse_btn, x, y, .. } => {
+                match mouse_btn {
+                    sdl2::mouse::MouseButton::Left => self.left_down = false,
+                    sdl2::mouse::MouseButton::Right => self.right_down = false,
+                    sdl2::mouse::MouseButton::Middle => {
+                        if let Some((x0, y0)) = self.middle_down_at.take() {
+                            if (x - x0).abs() <= CLICK_SLOP && (y - y0).abs() <= CLICK_SLOP {
+                                self.chord(x, y);
+                            }
+                        }
+                    },
+                    _ => {}
                 }
             },
             Event::MouseWheel { y, .. } => {
-                if let State::Playing = self.state {
+                if let Some(volume) = self.volume.as_mut().filter(|v| v.shown) {
+                    volume.camera.zoom(y);
+                } else if let State::Playing = self.game.state() {
                     self.camera.zoom += y;
                     if self.camera.zoom < 4 { self.camera.zoom = 4; }
                     if self.camera.zoom > 32 { self.camera.zoom = 32; }
                 }
             }
-            Event::KeyDown { scancode, .. } => {
+            Event::KeyDown { scancode, keymod, .. } => {
+                let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
                 if let Some(scancode) = scancode {
                     match scancode {
-                        Scancode::R => { 
-                            self.grid = Grid::new();
-                            self.state = State::Playing;
+                        Scancode::Z if ctrl => self.act(Action::Undo),
+                        Scancode::Y if ctrl => self.act(Action::Redo),
+                        Scancode::R => self.restart(rand::random()),
+                        Scancode::Num0 => self.set_board(None),
+                        Scancode::Num1 => self.set_board(Some(BoardSize::BEGINNER)),
+                        Scancode::Num2 => self.set_board(Some(BoardSize::INTERMEDIATE)),
+                        Scancode::Num3 => self.set_board(Some(BoardSize::EXPERT)),
+                        Scancode::H => match self.game.hint() {
+                            Some(pos) => println!("Hint: {:?} is safe", pos),
+                            None => println!("Hint: no safe cell can be deduced"),
+                        },
+                        Scancode::F => self.act(Action::AutoFlag),
+                        Scancode::P => {
+                            self.probabilities = match self.probabilities {
+                                Some(_) => None,
+                                None => Some(HashMap::new()),
+                            };
                         },
-                        Scancode::M => {
-                            self.grid.show_all_mines();
+                        Scancode::G => {
+                            self.leave_game();
+                            self.game.config.no_guess = !self.game.config.no_guess;
+                            println!("No guess: {}", self.game.config.no_guess);
+                            self.restart(rand::random());
+                        },
+                        Scancode::M => self.act(Action::ShowMines),
+                        Scancode::Tab => self.show_stats = !self.show_stats,
+                        Scancode::I => self.print_metrics(),
+                        Scancode::E => self.export_stats(),
+                        Scancode::V => if let Some(volume) = &mut self.volume {
+                            volume.shown = !volume.shown;
+                        },
+                        Scancode::L => if let Some(volume) = &mut self.volume {
+                            volume.next_axis();
+                        },
+                        Scancode::LeftBracket => if let Some(volume) = &mut self.volume {
+                            volume.peel(-1);
+                        },
+                        Scancode::RightBracket => if let Some(volume) = &mut self.volume {
+                            volume.peel(1);
+                        },
+                        Scancode::S => if let Some(volume) = &mut self.volume {
+                            volume.toggle_slice();
+                        },
+                        Scancode::F5 => {
+                            let time = std::time::SystemTime::now()
+                                .duration_since(std::time::UNIX_EPOCH)
+                                .map_or(0, |d| d.as_secs());
+                            self.save_replay(&format!("{}-{}", self.game.grid.seed(), time));
                         },
                         _ => {},
-                    }   
+                    }
                 }
             }
             _ => {}
@@ -94,295 +526,434 @@ impl GameState {
     }
 
     pub fn update(&mut self, input: &Input) {
-        if input.mouse.is_mouse_button_pressed(MouseButton::Middle) {
-            self.camera.position += Vec2i::new(- input.rel_mouse.x(), - input.rel_mouse.y());
-            self.grid.update_chunks(&self.camera);
+        if self.playback.is_some() {
+            self.play_replay();
+        } else {
+            if input.mouse.is_mouse_button_pressed(MouseButton::Middle) {
+                let (dx, dy) = (input.rel_mouse.x(), input.rel_mouse.y());
+                if let Some(volume) = self.volume.as_mut().filter(|v| v.shown) {
+                    volume.camera.orbit(dx, dy);
+                } else {
+                    self.camera.position += Vec2i::new(-dx, -dy);
+                    self.distance += ((dx * dx + dy * dy) as f32).sqrt() / self.camera.cell_size();
+                }
+            }
+            if self.game.state() == State::Playing {
+                self.elapsed += self.delta_time;
+            }
+            let camera = (self.camera.position.x, self.camera.position.y, self.camera.zoom);
+            if camera != self.recorded_camera {
+                self.record(Action::Camera { x: camera.0, y: camera.1, zoom: camera.2 });
+                self.recorded_camera = camera;
+            }
         }
-
-    }
-
-    pub fn draw(&self, renderer: &mut Renderer, resources: &Resources) {
-        Renderer::clear(Vec4 {x: 0.0, y: 0.0, z: 0.0, w: 0.0});
-        renderer.begin_2d();
-        renderer.default_texture();
-        
-        self.grid.draw(renderer, resources.get("./res/sprites.png").as_texture(), &self.camera);
-    }
-
-
-    pub fn on_left_click(&mut self, x: i32, y: i32) {
-        let pos = self.camera.screen_to_world(Vec2::new(x as f32, y as f32));
-        if self.grid.reveal(pos) {
-            self.loose();
+        self.cursor = Vec2::new(input.mouse.x() as f32, input.mouse.y() as f32);
+        self.sync_remote();
+        self.game.update();
+        self.tell_state();
+        if self.game.state() != State::Playing {
+            self.count_game();
+        }
+        let visible = self.camera.visible_chunks(self.game.grid.topology());
+        if let Err(e) = self.game.grid.update_chunks(visible) {
+            println!("Unable to save the world: {}", e);
+        }
+        if self.probabilities.is_some() && (self.probabilities_dirty || self.game.grid.is_revealing()) {
+            self.probabilities = Some(self.game.probabilities());
+            self.probabilities_dirty = false;
         }
     }
 
-    pub fn on_right_click(&mut self, x: i32, y: i32) {
-        let pos = self.camera.screen_to_world(Vec2::new(x as f32, y as f32));
-        self.grid.flag(pos);
+    /// Tells the player when the game ends.
+    fn tell_state(&mut self) {
+        let state = self.game.state();
+        if state == self.shown_state {
+            return;
+        }
+        self.shown_state = state;
+        match state {
+            State::Won => println!("You win !"),
+            State::Lost => println!("You loose !"),
+            State::Playing => {},
+        }
     }
 
-    fn loose(&mut self) {
-        println!("You loose !");
-        self.state = State::Lost;
-        self.grid.show_all_mines();
+    /// Tells the player about the `mines` an action set off, `lives` being those left before it.
+    fn tell_mines(&self, mines: u32, lives: u32) {
+        if mines == 0 || self.game.state() != State::Playing {
+            return;
+        }
+        // Only a click taken back costs no life
+        if self.game.lives() == lives {
+            println!("Boom ! That click is undone");
+        } else {
+            println!("Boom ! {} lives left", self.game.lives());
+        }
     }
-}
-
-#[derive(Copy, Clone)]
-enum CellContents {
-    Empty(i32),
-    Mine
-}
-
-#[derive(Copy, Clone)]
-struct Cell {
-    revealed: bool,
-    flag: bool,
-    contents: CellContents,
-}
 
-impl Default for Cell {
-    fn default() -> Self {
-        Self { revealed: false, flag:false, contents: CellContents::Empty(0) }
+    /// Tells the server what the player sees and points at, and takes in what changed.
+    /// Carries on alone with a new game once the server is gone.
+    fn sync_remote(&mut self) {
+        let visible = self.camera.visible_chunks(self.game.grid.topology());
+        let hovered = self.cell_under(self.cursor.x as i32, self.cursor.y as i32);
+        let Some(client) = &mut self.remote else {
+            return;
+        };
+        client.look_at(visible);
+        if let Some(pos) = hovered {
+            client.point_at(pos);
+        }
+        match client.poll(&mut self.game) {
+            Ok(received) => self.probabilities_dirty |= received,
+            Err(e) => {
+                println!("Connection to the server lost: {}", e);
+                self.remote = None;
+                self.restart(rand::random());
+            },
+        }
     }
-}
 
-const CELL_SIZE: i32 = 16;
-const CHUNK_SIZE: u32 = 16;
-const CHUNK_LEN: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
-
-struct Chunk {
-    elems : [Cell;(CHUNK_SIZE * CHUNK_SIZE) as usize],
-    position: Vec2i,
-}
-
-impl Chunk {
-    pub fn new(position: Vec2i, density: f32) -> Chunk {
-        let mut result = Chunk {
-            elems: [Default::default();256],
-            position,
+    /// Plays the actions of the replay that are due.
+    fn play_replay(&mut self) {
+        let Some(playback) = &mut self.playback else {
+            return;
         };
-        println!("Generating chunk {:?}", position);
-        let nb_mines: u32 = (density * (CHUNK_LEN) as f32) as u32;
-        for _ in 0..nb_mines {
-            loop {
-                let x = rand::random::<u32>() % CHUNK_SIZE as u32;
-                let y = rand::random::<u32>() % CHUNK_SIZE as u32;
-                let idx= Chunk::idx(x, y);
-                match result.elems[idx].contents {
-                    CellContents::Mine => continue,
-                    _ => { 
-                        result.place_mine(x, y);
-                        break;
-                    },
-                };
+        playback.clock += self.delta_time * 1000.0 * match playback.speed {
+            Speed::Normal => 1.0,
+            Speed::Fast => FAST_SPEED,
+            Speed::Stepped => 0.0,
+        };
+        while let Some(playback) = &self.playback {
+            match playback.replay.actions.get(playback.next) {
+                Some(a) if a.time as f32 <= playback.clock => self.step_replay(),
+                _ => break,
             }
         }
-        result
     }
 
-    fn idx(x: u32, y: u32) -> usize {
-        (x + y * CHUNK_SIZE) as usize
+    /// Plays the next action of the replay.
+    fn step_replay(&mut self) {
+        let Some(playback) = &mut self.playback else {
+            return;
+        };
+        let Some(next) = playback.replay.actions.get(playback.next).copied() else {
+            return;
+        };
+        playback.next += 1;
+        // Stepping moves the clock along, so that playing on resumes from there
+        playback.clock = playback.clock.max(next.time as f32);
+        if playback.next == playback.replay.actions.len() {
+            println!("End of the replay");
+        }
+        match next.action {
+            Action::Camera { x, y, zoom } => {
+                self.camera.position = Vec2i::new(x, y);
+                self.camera.zoom = zoom;
+            },
+            action => {
+                let lives = self.game.lives();
+                let revealed = action.apply(&mut self.game);
+                self.tell_mines(revealed.mines, lives);
+            },
+        }
+        self.probabilities_dirty = true;
     }
 
-    fn place_mine(&mut self, x: u32, y: u32) {
-        let idx = Chunk::idx(x, y);
-        self.elems[idx].contents = CellContents::Mine;
-        let start_x = if x == 0 { 0 } else { x - 1 };
-        let end_x = if x == CHUNK_SIZE - 1 { CHUNK_SIZE - 1 } else { x + 1 };
+    pub fn draw(&self, renderer: &mut Renderer, resources: &Resources) {
+        Renderer::clear(Vec4 {x: 0.0, y: 0.0, z: 0.0, w: 0.0});
+        renderer.begin_2d();
+        renderer.default_texture();
 
-        let start_y = if y == 0 { 0 } else { y - 1};
-        let end_y = if y == CHUNK_SIZE - 1 { CHUNK_SIZE - 1 } else { y + 1};
-        for x2 in start_x..=end_x {
-            for y2 in start_y..=end_y {
-                if x2 == x && y2 == y { continue; }
-                let idx = Chunk::idx(x2, y2);
-                if let CellContents::Empty(ref mut nb) = self.elems[idx].contents {
-                    *nb += 1;
-                }
+        if let Some(volume) = self.volume() {
+            let hovered = self.cell_under(self.cursor.x as i32, self.cursor.y as i32);
+            volume.draw(&self.game.grid, self.probabilities.as_ref(), hovered, renderer);
+            if let Some(probabilities) = &self.probabilities {
+                self.draw_hovered_probability(probabilities, renderer);
+            }
+            if self.show_stats {
+                self.draw_stats(renderer);
             }
+            return;
         }
-    }
 
-    pub fn show_all_mines(&mut self) {
-        for mut c in &mut self.elems {
-            if let CellContents::Mine = c.contents {
-                c.revealed = true;
-            }
+        let texture = resources.get("./res/sprites.png").as_texture();
+        texture.bind();
+        for c in self.game.grid.visible_chunks() {
+            draw_chunk(c, &self.game.grid, renderer, texture, &self.camera);
+        }
+        self.draw_large_numbers(renderer);
+        if let Some(client) = &self.remote {
+            self.draw_players(client, renderer);
+        }
+        if self.game.config.lives > 1 {
+            self.draw_lives(renderer);
+        }
+        if let Some(probabilities) = &self.probabilities {
+            self.draw_probabilities(probabilities, renderer);
+        }
+        if self.show_stats {
+            self.draw_stats(renderer);
         }
     }
 
-    pub fn draw(&self, renderer: &mut Renderer, texture: &Texture, camera: &Camera) {
-        let cell_size = camera.cell_size();
-        let origin = self.position.vec2() * CHUNK_SIZE as f32 * cell_size;
-        let origin = origin - camera.position.vec2();
-        for i in 0..self.elems.len() {
-            let x = i as u32 % CHUNK_SIZE;
-            let y = i as u32 / CHUNK_SIZE;
-            let p0 = Vec2::new(x as f32 * cell_size, y as f32 * cell_size) + origin;
-            let p1 = p0 + Vec2::new(cell_size, cell_size);
-
-            let uv_size = texture.get_sprite_size();
-            
-            let bckg = if !self.elems[i].revealed { SPRITE_HIDDEN } else { SPRITE_0 };
-            let uv0 = texture.get_uv(bckg.0, bckg.1);
-            renderer.push_2d_sprite(p0, p1, uv0, uv0 + uv_size); // Background
-            if !self.elems[i].revealed { 
-                if self.elems[i].flag {
-                    let uv0 = texture.get_uv(SPRITE_FLAG.0, SPRITE_FLAG.1);
-                    renderer.push_2d_sprite(p0, p1, uv0, uv0 + uv_size); // Background
+    /// Numbers above 8 and negative ones have no sprite, they are written over their cell.
+    fn draw_large_numbers(&self, renderer: &mut Renderer) {
+        let topology = self.game.grid.topology();
+        let scale = (self.camera.cell_size() / 16.0).floor().max(1.0);
+        let mut numbers = Vec::new();
+        for chunk in self.game.grid.visible_chunks() {
+            for (local, cell) in chunk.cells() {
+                match cell.contents {
+                    CellContents::Empty(nb) if !(0..=8).contains(&nb) && cell.revealed => numbers.push((chunk.position().world(local), nb)),
+                    _ => {},
                 }
-             } else {
-                match self.elems[i].contents {
-                    CellContents::Empty(nb) => {
-                        if nb != 0 {
-                            let uv0 = texture.get_uv((nb - 1) % 4, (nb - 1) /4);
-                            renderer.push_2d_sprite(p0, p1, uv0, uv0 + uv_size); 
-                        }
-                    },
-                    CellContents::Mine => {
-                        let uv0 = texture.get_uv(SPRITE_MINE.0, SPRITE_MINE.1);
-                        renderer.push_2d_sprite(p0, p1, uv0, uv0 + uv_size);
-                    }
-                };
             }
         }
-    }
-
-    pub fn get_cell(&self, pos: (u32, u32)) -> Option<&Cell> {
-        if pos.0 >= CHUNK_SIZE || pos.1 >= CHUNK_SIZE {
-            return None;
+        if numbers.is_empty() {
+            return;
+        }
+        renderer.flush();
+        renderer.default_texture();
+        for (pos, nb) in numbers {
+            let text = nb.to_string();
+            let center = self.camera.world_to_screen(topology.center(pos));
+            draw_text(renderer, &text, center - text_size(&text, scale) / 2.0, scale, Vec4::new(0.5, 0.0, 0.0, 1.0));
         }
-        self.elems.get(Chunk::idx(pos.0, pos.1))
     }
 
-    pub fn get_cell_mut(&mut self, pos: (u32, u32)) -> Option<&mut Cell> {
-        self.elems.get_mut(Chunk::idx(pos.0, pos.1))
+    /// Tints the cells each player revealed with their colour, and marks the cells the others point at.
+    fn draw_players(&self, client: &Client, renderer: &mut Renderer) {
+        renderer.flush();
+        renderer.default_texture();
+        for chunk in self.game.grid.visible_chunks() {
+            for (local, _) in chunk.cells() {
+                let pos = chunk.position().world(local);
+                if let Some(owner) = client.owner(pos) {
+                    self.fill_cell(renderer, pos, player_color(owner, 0.2));
+                }
+            }
+        }
+        for (player, pos) in client.cursors() {
+            self.fill_cell(renderer, *pos, player_color(*player, 0.7));
+        }
     }
-}
 
-struct Grid {
-    chunks: Vec<Chunk>
-}
-
-impl Grid {
-    pub fn new() -> Grid {
-         Grid {
-             chunks: Vec::new()
-        }
-    }
-
-    pub fn update_chunks(&mut self, camera: &Camera) {
-        // Calculate the cameras extent in chunks. 
-        // We approximate with the following values for the default zoom level:
-        const NB_CHUNKS_HEIGHT : f32 = 4.0;
-        const NB_CHUNKS_WIDTH : f32 = 5.0;
-
-        let nb_h = NB_CHUNKS_HEIGHT * camera.cell_size() / CELL_SIZE as f32;
-        let nb_w = NB_CHUNKS_WIDTH  * camera.cell_size() / CELL_SIZE as f32;
-        let min_extent = camera.position / (camera.cell_size() as i32 * CHUNK_SIZE as i32);
-        let min_extent = min_extent - Vec2i::new(1, 1);
-        let max_extent = min_extent + Vec2i::new(nb_h.ceil() as i32, nb_w.ceil() as i32);
-        let max_extent = max_extent + Vec2i::new(2, 0);
-        self.chunks.retain(|x| x.position.x >= min_extent.x && x.position.x <= max_extent.x && x.position.y >= min_extent.y && x.position.y <= max_extent.y);
-        for x in min_extent.x..=max_extent.x {
-            for y in min_extent.y..=max_extent.y {
-                let pos = Vec2i::new(x, y);
-                if let Some(_) = self.find_chunk(pos) {
-                    continue;
+    /// Marks the mines set off and the cells they lock, and writes the lives left.
+    fn draw_lives(&self, renderer: &mut Renderer) {
+        renderer.flush();
+        renderer.default_texture();
+        if self.game.state() == State::Playing {
+            for chunk in self.game.grid.visible_chunks() {
+                for (local, cell) in chunk.cells() {
+                    if !(cell.revealed && cell.is_mine()) {
+                        continue;
+                    }
+                    let pos = chunk.position().world(local);
+                    for p in self.game.lock_area(pos) {
+                        self.fill_cell(renderer, p, Vec4::new(0.0, 0.0, 0.0, 0.35));
+                    }
+                    self.fill_cell(renderer, pos, Vec4::new(1.0, 0.0, 0.0, 0.5));
                 }
-                self.chunks.push(Chunk::new(pos, DENSITY));
             }
         }
 
+        let text = format!("lives {}/{}", self.game.lives(), self.game.config.lives);
+        let scale = 3.0;
+        let size = text_size(&text, scale);
+        let pos = Vec2::new(WINDOW_SIZE.0 as f32 - size.x - 16.0, 16.0);
+        renderer.push_2d_quad(pos.x - scale, pos.y - scale, size.x + 2.0 * scale, size.y + 2.0 * scale, Vec4::new(0.0, 0.0, 0.0, 0.8));
+        let color = if self.game.lives() > 1 { Vec4::new(1.0, 1.0, 1.0, 1.0) } else { Vec4::new(1.0, 0.3, 0.3, 1.0) };
+        draw_text(renderer, &text, pos, scale, color);
     }
 
-    pub fn draw(&self, renderer: &mut Renderer, texture: &Texture, camera: &Camera) {
-        texture.bind();
-        for c in &self.chunks {
-            c.draw(renderer, texture, camera);
+    /// A table of the statistics of every mode, then the best games of the current one.
+    fn draw_stats(&self, renderer: &mut Renderer) {
+        renderer.flush();
+        renderer.default_texture();
+        let scale = 2.0;
+        let line = 8.0 * scale;
+        let mut lines = vec![format!(
+            "{:<24}{:>7}{:>7}{:>10}{:>9}{:>8}{:>7}{:>10}{:>10}",
+            "mode", "played", "won", "streak", "best", "3bv/s", "eff", "revealed", "distance",
+        )];
+        for (name, s) in &self.profile.modes {
+            lines.push(format!(
+                "{:<24}{:>7}{:>6.0}%{:>10}{:>9}{:>8.2}{:>6.0}%{:>10}{:>10.0}",
+                name, s.played, s.win_rate() * 100.0, format!("{}/{}", s.streak, s.best_streak),
+                s.best_time().map_or("-".to_string(), |t| format!("{:.2}", t as f32 / 1000.0)),
+                s.bbbv_per_second(), s.efficiency() * 100.0, s.revealed, s.distance,
+            ));
         }
-    }
-
-    fn find_chunk(&self, chunk_coord: Vec2i) -> Option<&Chunk> {
-        for c in &self.chunks {
-            if c.position == chunk_coord {
-                return Some(c);
-            }
+        let mode = self.game.config.mode();
+        lines.push(String::new());
+        lines.push(format!("best games - {}", mode));
+        lines.push(format!("{:<6}{:>9}{:>7}{:>8}{:>7}", "", "time", "3bv", "3bv/s", "eff"));
+        for (i, g) in self.profile.modes.get(&mode).map_or(&[][..], |s| &s.best).iter().enumerate() {
+            lines.push(format!(
+                "{:<6}{:>9.2}{:>7}{:>8.2}{:>6.0}%",
+                i + 1, g.time as f32 / 1000.0, g.bbbv, g.bbbv_per_second(), g.efficiency() * 100.0,
+            ));
+        }
+        lines.push(String::new());
+        lines.push("tab: close  e: export".to_string());
+
+        let width = lines.iter().map(|l| text_size(l, scale).x).fold(0.0, f32::max);
+        let pos = Vec2::new(40.0, 40.0);
+        let margin = 4.0 * scale;
+        renderer.push_2d_quad(
+            pos.x - margin, pos.y - margin,
+            width + 2.0 * margin, lines.len() as f32 * line + 2.0 * margin,
+            Vec4::new(0.0, 0.0, 0.0, 0.85),
+        );
+        for (i, l) in lines.iter().enumerate() {
+            draw_text(renderer, l, pos + Vec2::new(0.0, i as f32 * line), scale, Vec4::new(1.0, 1.0, 1.0, 1.0));
         }
-        None
     }
 
-    fn find_chunk_mut(&mut self, chunk_coord: Vec2i) -> Option<&mut Chunk> {
-        for c in &mut self.chunks {
-            if c.position == chunk_coord {
-                return Some(c);
+    /// Tints hidden cells from green to red as they are more likely to be mines,
+    /// then writes the probability of the cell under the cursor.
+    fn draw_probabilities(&self, probabilities: &HashMap<WorldPos, f32>, renderer: &mut Renderer) {
+        renderer.flush();
+        renderer.default_texture();
+        for (pos, p) in probabilities {
+            if !self.game.grid.get_cell(*pos).is_some_and(|c| !c.revealed && !c.is_flagged()) {
+                continue;
             }
+            self.fill_cell(renderer, *pos, Vec4::new(*p, 1.0 - *p, 0.0, 0.45));
         }
-        None
+        self.draw_hovered_probability(probabilities, renderer);
     }
 
-    fn get_cell(&self, pos: Vec2i) -> Option<&Cell> {
-        let chunk = self.find_chunk(pos / CHUNK_SIZE as i32)?;
-        let x = pos.x - chunk.position.x * CHUNK_SIZE as i32;
-        let y = pos.y - chunk.position.y * CHUNK_SIZE as i32;
-        chunk.get_cell((x as u32, y as u32))
+    /// Writes the probability of the cell under the cursor next to it.
+    fn draw_hovered_probability(&self, probabilities: &HashMap<WorldPos, f32>, renderer: &mut Renderer) {
+        let hovered = self.cell_under(self.cursor.x as i32, self.cursor.y as i32);
+        if let Some(p) = hovered.and_then(|h| probabilities.get(&h)) {
+            let text = format!("{:.0}%", p * 100.0);
+            let scale = 2.0;
+            let size = text_size(&text, scale);
+            let pos = self.cursor + Vec2::new(12.0, 12.0);
+            renderer.push_2d_quad(pos.x - scale, pos.y - scale, size.x + 2.0 * scale, size.y + 2.0 * scale, Vec4::new(0.0, 0.0, 0.0, 0.8));
+            draw_text(renderer, &text, pos, scale, Vec4::new(1.0, 1.0, 1.0, 1.0));
+        }
     }
 
-    fn get_cell_mut(&mut self, pos: Vec2i) -> Option<&mut Cell> {
-        let chunk = self.find_chunk_mut(pos / CHUNK_SIZE as i32)?;
-        let x = pos.x - chunk.position.x * CHUNK_SIZE as i32;
-        let y = pos.y - chunk.position.y * CHUNK_SIZE as i32;
-        chunk.get_cell_mut((x as u32, y as u32))
+    /// Covers the cell at `pos` with `color`. Expects the default texture to be bound.
+    fn fill_cell(&self, renderer: &mut Renderer, pos: WorldPos, color: Vec4) {
+        let corners: Vec<Vec2> = self.game.grid.topology().polygon(pos).into_iter()
+            .map(|p| self.camera.world_to_screen(p))
+            .collect();
+        renderer.push_2d_polygon(&corners, Vec2::new(0.5, 0.5), color);
     }
 
-    pub fn reveal(&mut self, pos: Vec2i) -> bool {
-        let mut cell = self.get_cell_mut(pos).unwrap();
-        cell.revealed = true;
-        if let CellContents::Mine = cell.contents { return true; }
-        if let CellContents::Empty(x) = cell.contents { if x != 0 { return false; } }
-        self.reveal_recurse(pos, 0);
-        return false;
+    /// Left click reveals, or chords on an already revealed number or with the right button held.
+    pub fn on_left_click(&mut self, x: i32, y: i32) {
+        let Some(pos) = self.cell_under(x, y) else {
+            return;
+        };
+        let revealed = self.game.grid.get_cell(pos).is_some_and(|c| c.revealed);
+        if self.right_down || revealed {
+            self.chord(x, y);
+        } else {
+            self.act(Action::Reveal(pos));
+        }
     }
 
-    pub fn reveal_recurse(&mut self, pos: Vec2i, depth: u32) {
-        if depth >= 8 {
+    pub fn on_right_click(&mut self, x: i32, y: i32) {
+        if self.left_down {
+            self.chord(x, y);
             return;
         }
-        let mut cell = self.get_cell_mut(pos).unwrap();
-        cell.revealed = true;
-
-        fn check_cell(cell: &Cell) -> bool {
-            if cell.revealed == true { 
-                return false;
-            } 
-            match cell.contents {
-                CellContents::Empty(_) => true,
-                CellContents::Mine => false,
-            }
+        if let Some(pos) = self.cell_under(x, y) {
+            self.act(Action::Flag(pos));
         }
+    }
 
-        let adj = [Vec2i::new(-1, 0), Vec2i::new(1, 0), Vec2i::new(0, -1), Vec2i::new(0, 1)];
-        for p in adj {
-            let pos = pos + p;
-            if let Some(c) = self.get_cell(pos) {
-                if check_cell(c) {
-                    self.reveal_recurse(pos, depth + 1);
-                }
-            }  
+    pub fn chord(&mut self, x: i32, y: i32) {
+        if let Some(pos) = self.cell_under(x, y) {
+            self.act(Action::Chord(pos));
         }
     }
 
-    pub fn flag(&mut self, pos: Vec2i) {
-        if let Some(cell) = self.get_cell_mut(pos) {
-            cell.flag = !cell.flag;
+    /// Saves the game to resume it on the next start, if it isn't over.
+    pub fn quit(&mut self) {
+        self.end_recording();
+        if self.playback.is_some() || self.remote.is_some() {
+            return;
+        }
+        let path = Path::new(SAVE_FILE);
+        if self.game.state() != State::Playing {
+            if path.exists() {
+                if let Err(e) = std::fs::remove_file(path) {
+                    println!("Unable to remove {}: {}", path.display(), e);
+                }
+            }
+            return;
+        }
+        let camera = (self.camera.position.x, self.camera.position.y, self.camera.zoom);
+        let saved = SavedGame::new(&mut self.game, (self.elapsed * 1000.0) as u64, camera, self.distance);
+        match saved.and_then(|s| s.save(path)) {
+            Ok(()) => println!("Game saved to {}", path.display()),
+            Err(e) => println!("Unable to save the game to {}: {}", path.display(), e),
         }
     }
+}
+
+/// Colour of `player` in cooperative games.
+fn player_color(player: u8, alpha: f32) -> Vec4 {
+    const COLORS: [(f32, f32, f32); 8] = [
+        (0.2, 0.4, 1.0), (1.0, 0.3, 0.2), (0.2, 0.8, 0.3), (1.0, 0.8, 0.1),
+        (0.8, 0.3, 0.9), (0.1, 0.8, 0.8), (1.0, 0.5, 0.1), (0.9, 0.4, 0.6),
+    ];
+    let (r, g, b) = COLORS[player as usize % COLORS.len()];
+    Vec4::new(r, g, b, alpha)
+}
 
-    pub fn show_all_mines(&mut self) {
-        for c in &mut self.chunks {
-            c.show_all_mines();
+/// Square cells are drawn with their sprites. Other shapes are filled with the colour in the middle
+/// of their background sprite, with the flag, mine or number sprite in their middle.
+fn draw_chunk(chunk: &Chunk, grid: &Grid, renderer: &mut Renderer, texture: &Texture, camera: &Camera) {
+    let topology = grid.topology();
+    let uv_size = texture.get_sprite_size();
+    let white = Vec4::new(1.0, 1.0, 1.0, 1.0);
+    for (local, cell) in chunk.cells() {
+        let pos = chunk.position().world(local);
+        if !grid.contains(pos) {
+            continue;
+        }
+        let corners: Vec<Vec2> = topology.polygon(pos).into_iter().map(|p| camera.world_to_screen(p)).collect();
+
+        let bckg = if !cell.revealed { SPRITE_HIDDEN } else { SPRITE_0 };
+        let uv0 = texture.get_uv(bckg.0, bckg.1);
+        let (p0, p1) = if corners.len() == 4 {
+            renderer.push_2d_sprite(corners[0], corners[2], uv0, uv0 + uv_size); // Background
+            (corners[0], corners[2])
+        } else {
+            let center = camera.world_to_screen(topology.center(pos));
+            let inset: Vec<Vec2> = corners.iter().map(|c| center + (*c - center) * CELL_INSET).collect();
+            renderer.push_2d_polygon(&inset, uv0 + uv_size / 2.0, white);
+            let half = Vec2::new(ICON_SIZE, ICON_SIZE) * camera.cell_size() / 2.0;
+            (center - half, center + half)
+        };
+        if !cell.revealed {
+            if cell.is_flagged() {
+                let sprite = flag_sprite(cell.flag);
+                let uv0 = texture.get_uv(sprite.0, sprite.1);
+                renderer.push_2d_sprite(p0, p1, uv0, uv0 + uv_size);
+            }
+        } else {
+            match cell.contents {
+                CellContents::Empty(nb) => {
+                    // Larger and negative numbers are written by `draw_large_numbers`
+                    if (1..=8).contains(&nb) {
+                        let uv0 = texture.get_uv((nb - 1) % 4, (nb - 1) /4);
+                        renderer.push_2d_sprite(p0, p1, uv0, uv0 + uv_size);
+                    }
+                },
+                CellContents::Mine(weight) => {
+                    let sprite = mine_sprite(weight);
+                    let uv0 = texture.get_uv(sprite.0, sprite.1);
+                    renderer.push_2d_sprite(p0, p1, uv0, uv0 + uv_size);
+                }
+            };
         }
     }
 }
@@ -18,6 +18,14 @@ mod sprite_sheet;
 mod resources;
 use resources::*;
 
+mod text;
+
+mod volume;
+
+use minesweeper_core::{Client, Config, Replay, SavedGame};
+
+pub const WINDOW_SIZE: (u32, u32) = (1280, 720);
+
 fn main() {
     let sdl_context = sdl2::init().expect("SDL: Failed to init SDL");
 
@@ -26,7 +34,7 @@ fn main() {
         .video()
         .expect("SDL: Failed to init Video subsystem");
     let window = video
-        .window("Minesweeper", 1280, 720)
+        .window("Minesweeper", WINDOW_SIZE.0, WINDOW_SIZE.1)
         .position_centered()
         .opengl()
         .build()
@@ -39,8 +47,84 @@ fn main() {
     resources.get("./res/sprites.png").as_texture().bind();
     Texture::set_filter(Filter::Nearest);
 
-    // Init game
-    let mut game_state = GameState::new();
+    // Init game. An optional seed on the command line replays a given world,
+    // a board size (`beginner`, `intermediate`, `expert` or `WxHxM`) plays a finite board,
+    // `no-guess` places mines so that no guess is needed and `casual` takes back losing clicks.
+    // `replay <file>` watches a replay instead, `fast` or `step` setting how.
+    // `resume` carries on with the game saved on quit, `lives <n>` lets the player set off n - 1 mines and carry on,
+    // `lock <radius>` setting how many neighbours away from a mine set off cells are out of reach, up to 5.
+    // `density <curve>` sets the mine density of the infinite world, e.g. `0.05-0.2/2000~0.02`
+    // going from 5% at the origin to 20% 2000 cells away, each chunk varying by up to 2%. Openings of
+    // sparse worlds may have no end, they open as far as the screen shows.
+    // `hex`, `triangle` or `torus` changes the shape of the cells, the torus wrapping a finite board around.
+    // `voxel` plays a finite board in 3D, its rows split into `layers <n>` counting 26 neighbours,
+    // e.g. `8x24x40 voxel layers 3` for a 8x8x3 volume of 40 mines.
+    // `rule <neighbourhood>` sets which squares numbers count: `cross`, `knight`, `extended` (5x5),
+    // a mask such as `x.x/.o./x.x`, or a file holding a mask one row per line. Masks are symmetric,
+    // a cell counting its neighbours being counted by them.
+    // `weights <double>/<triple>/<negative>` gives mines a chance to count twice, three times,
+    // or to take one off the numbers around, e.g. `0.2/0.1/0.1`. Flags then cycle through those weights.
+    // `join <address>` plays the cooperative game of a `minesweeper-server`, e.g. `join 127.0.0.1:7777`,
+    // R asking it for a new world once the game is lost.
+    let mut seed = rand::random();
+    let mut config = Config::default();
+    let mut replay = None;
+    let mut speed = Speed::Normal;
+    let mut resume = false;
+    let mut join = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "replay" => replay = args.next(),
+            "fast" => speed = Speed::Fast,
+            "step" => speed = Speed::Stepped,
+            "resume" => resume = true,
+            "join" => join = args.next(),
+            _ => match arg.parse() {
+                Ok(s) => seed = s,
+                Err(_) => if let Err(e) = config.parse_arg(&arg, &mut args) {
+                    println!("Ignoring {}", e);
+                },
+            },
+        }
+    }
+    if let Err(e) = config.check() {
+        println!("Ignoring board: {}", e);
+        config.board = None;
+    }
+    if let Err(e) = config.check() {
+        println!("Unable to play: {}", e);
+        return;
+    }
+    let save_file = std::path::Path::new(SAVE_FILE);
+    if !resume && replay.is_none() && join.is_none() && save_file.exists() {
+        println!("A game was left unfinished, run with `resume` to carry on with it");
+    }
+    let saved = resume.then(|| SavedGame::load(save_file));
+    let joined = join.and_then(|address| match Client::connect(address.as_str()) {
+        Ok(joined) => Some(joined),
+        Err(e) => {
+            println!("Unable to join {}: {}", address, e);
+            None
+        },
+    });
+    let mut game_state = if let Some((client, game)) = joined {
+        GameState::with_client(client, game)
+    } else {
+        match (replay.map(|path| Replay::load(std::path::Path::new(&path))), saved) {
+            (Some(Ok(replay)), _) => GameState::with_replay(replay, speed),
+            (Some(Err(e)), _) => {
+                println!("Unable to load replay: {}", e);
+                GameState::with_config(seed, config)
+            },
+            (None, Some(Ok(saved))) => GameState::with_save(&saved),
+            (None, Some(Err(e))) => {
+                println!("Unable to resume the game: {}", e);
+                GameState::with_config(seed, config)
+            },
+            (None, None) => GameState::with_config(seed, config),
+        }
+    };
 
     let mut previous_frame = std::time::Instant::now();
 
@@ -51,7 +135,10 @@ fn main() {
 
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit { .. } => break 'running,
+                Event::Quit { .. } => {
+                    game_state.quit();
+                    break 'running;
+                },
                 _ => { 
                     game_state.event(event)
                 }
@@ -13,6 +13,11 @@ pub struct Renderer {
     default_texture: Texture,
 }
 
+/// Projection of the 3D view, for a window of `width` x `height`.
+pub fn perspective(width: u32, height: u32) -> Mat4 {
+    cgmath::perspective(cgmath::Deg(60.0), (width as f32) / (height as f32), 0.1, 1000.0)
+}
+
 impl Renderer {
     #[must_use]
     pub fn new(window: &Window, video: &sdl2::VideoSubsystem) -> Result<Renderer, Error> {
@@ -93,12 +98,7 @@ impl Renderer {
 
     pub fn set_projection_matrix(&self, width: u32, height: u32) {
         self.screen_space_shader.set_uniform("Projection", cgmath::ortho(0.0, width as f32, height as f32, 0.0, -1.0, 1.0)).unwrap();
-        self.shader.set_uniform("Projection", cgmath::perspective(
-            cgmath::Deg(60.0),
-            (width as f32) / (height as f32),
-            0.1,
-            1000.0,
-        )).unwrap();
+        self.shader.set_uniform("Projection", perspective(width, height)).unwrap();
 
     }
 
@@ -121,6 +121,16 @@ impl Renderer {
         self.push_vertex(Vertex { pos: Vec3::new(x, y + h, 0.0), normal, uv: Vec2::new(0.0, 1.0), color, });
     }
 
+    /// Fills a convex polygon, as a fan of triangles from its first corner, sampling the texture at `uv` only.
+    pub fn push_2d_polygon(&mut self, corners: &[Vec2], uv: Vec2, color: Vec4) {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        for i in 1..corners.len().saturating_sub(1) {
+            for p in [corners[0], corners[i], corners[i + 1]] {
+                self.push_vertex(Vertex { pos: Vec3::new(p.x, p.y, 0.0), normal, uv, color });
+            }
+        }
+    }
+
     pub fn push_2d_sprite(&mut self, p0: Vec2, p1: Vec2, uv0: Vec2, uv1: Vec2) {
         let normal = Vec3::new(0.0, 0.0, 1.0);
         let color = Vector4 { x: 1.0, y: 1.0, z: 1.0, w: 1.0 };
@@ -142,51 +152,57 @@ impl Renderer {
     }
 
     pub fn push_cube(&mut self, color: Vec4) {
+        self.push_cube_at(Vec3::new(0.0, 0.0, 0.0), 1.0, color);
+    }
+
+    /// A cube of side `size` around `center`, without having to change the model matrix.
+    pub fn push_cube_at(&mut self, center: Vec3, size: f32, color: Vec4) {
+        let corner = |x: f32, y: f32, z: f32| (center + Vec3::new(x, y, z) * size).point3();
         self.push_quad_corners(
-            Point3 { x: -0.5, y: -0.5, z: -0.5, },
-            Point3 { x: 0.5, y: -0.5, z: -0.5, },
-            Point3 { x: 0.5, y: -0.5, z: 0.5, },
-            Point3 { x: -0.5, y: -0.5, z: 0.5, },
+            corner(-0.5, -0.5, -0.5),
+            corner(0.5, -0.5, -0.5),
+            corner(0.5, -0.5, 0.5),
+            corner(-0.5, -0.5, 0.5),
             UP,
             color,
         ); // -Y
         self.push_quad_corners(
-            Point3 { x: -0.5, y: 0.5, z: -0.5, },
-            Point3 { x: 0.5, y: 0.5, z: -0.5, },
-            Point3 { x: 0.5, y: 0.5, z: 0.5, },
-            Point3 { x: -0.5, y: 0.5, z: 0.5, },
+            corner(-0.5, 0.5, -0.5),
+            corner(0.5, 0.5, -0.5),
+            corner(0.5, 0.5, 0.5),
+            corner(-0.5, 0.5, 0.5),
             DOWN,
             color,
         ); // +Y
         self.push_quad_corners(
-            Point3 { x: -0.5, y: -0.5, z: -0.5, },
-            Point3 { x: -0.5, y: 0.5, z: -0.5, },
-            Point3 { x: -0.5, y: 0.5, z: 0.5, },
-            Point3 { x: -0.5, y: -0.5, z: 0.5, },
+            corner(-0.5, -0.5, -0.5),
+            corner(-0.5, 0.5, -0.5),
+            corner(-0.5, 0.5, 0.5),
+            corner(-0.5, -0.5, 0.5),
             LEFT,
             color,
         ); // -X
         self.push_quad_corners(
-            Point3 { x: 0.5, y: -0.5, z: -0.5, },
-            Point3 { x: 0.5, y: 0.5, z: -0.5, },
-            Point3 { x: 0.5, y: 0.5, z: 0.5, },
-            Point3 { x: 0.5, y: -0.5, z: 0.5, },
+            corner(0.5, -0.5, -0.5),
+            corner(0.5, 0.5, -0.5),
+            corner(0.5, 0.5, 0.5),
+            corner(0.5, -0.5, 0.5),
             RIGHT,
             color,
         ); // +X
         self.push_quad_corners(
-            Point3 { z: -0.5, y: -0.5, x: -0.5, },
-            Point3 { z: -0.5, y: 0.5, x: -0.5, },
-            Point3 { z: -0.5, y: 0.5, x: 0.5, },
-            Point3 { z: -0.5, y: -0.5, x: 0.5, },
+            corner(-0.5, -0.5, -0.5),
+            corner(-0.5, 0.5, -0.5),
+            corner(0.5, 0.5, -0.5),
+            corner(0.5, -0.5, -0.5),
             BACK,
             color,
         ); // -Z
         self.push_quad_corners(
-            Point3 { z: 0.5, y: -0.5, x: -0.5, },
-            Point3 { z: 0.5, y: 0.5, x: -0.5, },
-            Point3 { z: 0.5, y: 0.5, x: 0.5, },
-            Point3 { z: 0.5, y: -0.5, x: 0.5, },
+            corner(-0.5, -0.5, 0.5),
+            corner(-0.5, 0.5, 0.5),
+            corner(0.5, 0.5, 0.5),
+            corner(0.5, -0.5, 0.5),
             FORWARD,
             color,
         ); // +Z
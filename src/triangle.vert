@@ -12,11 +12,15 @@ out vec3 Normal;
 out vec2 UV;
 out vec3 FragPos;
 out vec3 VertexColor;
+out vec3 ViewPos;
+out vec3 ViewNormal;
 
 void main() {
     Normal = aNormal;
     UV = aUv;
     VertexColor = aColor;
     FragPos = vec3(Model * vec4(aPos, 1.0));
-    gl_Position = Projection * View * vec4(FragPos, 1.0);
+    ViewPos = vec3(View * vec4(FragPos, 1.0));
+    ViewNormal = mat3(View) * mat3(Model) * aNormal;
+    gl_Position = Projection * vec4(ViewPos, 1.0);
 }
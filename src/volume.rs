@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use minesweeper_core::*;
+
+use crate::{collision::AABB, math::*, renderer::*, text::*, WINDOW_SIZE};
+
+/// Side of a hidden or flagged cube, leaving a gap between neighbours.
+const CUBE_SIZE: f32 = 0.9;
+/// Side of the cube standing for a revealed number.
+const NUMBER_SIZE: f32 = 0.3;
+/// Radians the camera turns per pixel dragged.
+const ORBIT_SPEED: f32 = 0.01;
+
+/// Looks at `target` from `distance` away, turning around it.
+pub struct OrbitCamera {
+    pub yaw: f32,
+    /// Angle from straight above, kept off the poles so that up stays up.
+    pub pitch: f32,
+    pub distance: f32,
+    target: Vec3,
+}
+
+impl OrbitCamera {
+    pub fn eye(&self) -> Point3 {
+        (self.target - forward_from_yaw_pitch(self.yaw, self.pitch) * self.distance).point3()
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye(), self.target.point3(), UP)
+    }
+
+    /// Turns the camera by a mouse drag of `(dx, dy)` pixels.
+    pub fn orbit(&mut self, dx: i32, dy: i32) {
+        self.yaw += dx as f32 * ORBIT_SPEED;
+        self.pitch = (self.pitch + dy as f32 * ORBIT_SPEED).clamp(0.05, std::f32::consts::PI - 0.05);
+    }
+
+    /// Moves closer for positive `steps` of the mouse wheel, away for negative ones.
+    pub fn zoom(&mut self, steps: i32) {
+        self.distance = (self.distance * 0.9f32.powi(steps)).clamp(2.0, 500.0);
+    }
+
+    /// Origin and direction of the ray going through the point `cursor` of the screen.
+    pub fn ray(&self, cursor: Vec2) -> (Point3, Vec3) {
+        let (w, h) = WINDOW_SIZE;
+        let x = cursor.x / w as f32 * 2.0 - 1.0;
+        let y = 1.0 - cursor.y / h as f32 * 2.0;
+        let inverse = (perspective(w, h) * self.view()).invert().unwrap();
+        let unproject = |z: f32| {
+            let p = inverse * Vec4::new(x, y, z, 1.0);
+            Vec3::new(p.x, p.y, p.z) / p.w
+        };
+        let (near, far) = (unproject(-1.0), unproject(1.0));
+        (near.point3(), (far - near).normalize())
+    }
+
+    /// Where `p` is on screen, `None` behind the camera.
+    pub fn project(&self, p: Vec3) -> Option<Vec2> {
+        let (w, h) = WINDOW_SIZE;
+        let clip = perspective(w, h) * self.view() * p.extend(1.0);
+        (clip.w > 0.0).then(|| Vec2::new(
+            (clip.x / clip.w + 1.0) / 2.0 * w as f32,
+            (1.0 - clip.y / clip.w) / 2.0 * h as f32,
+        ))
+    }
+}
+
+/// A voxel board seen in 3D. Layers along one axis can be peeled off from the front,
+/// or shown one at a time, to see inside the volume.
+pub struct VolumeView {
+    voxel: Voxel,
+    pub camera: OrbitCamera,
+    /// Drawn in 3D rather than as layers side by side.
+    pub shown: bool,
+    /// Axis layers are peeled along: 0 for x, 1 for y, 2 for z.
+    axis: usize,
+    /// Layers before this one along `axis` are hidden.
+    peel: i32,
+    /// Only the `peel` layer is shown.
+    slice: bool,
+}
+
+impl VolumeView {
+    pub fn new(voxel: Voxel) -> VolumeView {
+        let largest = voxel.width.max(voxel.height).max(voxel.depth) as f32;
+        VolumeView {
+            voxel,
+            camera: OrbitCamera { yaw: 0.6, pitch: 2.0, distance: largest * 2.0, target: Vec3::new(0.0, 0.0, 0.0) },
+            shown: true,
+            axis: 2,
+            peel: 0,
+            slice: false,
+        }
+    }
+
+    fn layers(&self) -> i32 {
+        [self.voxel.width, self.voxel.height, self.voxel.depth][self.axis] as i32
+    }
+
+    /// Peels layers along the next axis, starting with all of them shown.
+    pub fn next_axis(&mut self) {
+        self.axis = (self.axis + 1) % 3;
+        self.peel = 0;
+    }
+
+    /// Hides `n` more layers, or shows `-n` of them back.
+    pub fn peel(&mut self, n: i32) {
+        self.peel = (self.peel + n).clamp(0, self.layers() - 1);
+    }
+
+    pub fn toggle_slice(&mut self) {
+        self.slice = !self.slice;
+    }
+
+    fn is_shown(&self, voxel: [i32; 3]) -> bool {
+        let coord = voxel[self.axis];
+        if self.slice { coord == self.peel } else { coord >= self.peel }
+    }
+
+    /// Middle of a cell in the world, the volume being centered on the origin with y going up.
+    fn center(&self, [x, y, z]: [i32; 3]) -> Vec3 {
+        let half = |n: u32| (n as f32 - 1.0) / 2.0;
+        Vec3::new(x as f32 - half(self.voxel.width), half(self.voxel.height) - y as f32, z as f32 - half(self.voxel.depth))
+    }
+
+    /// Cells of the layers shown, with what is drawn of them: the side of their cube and its colour.
+    fn cubes<'a>(&'a self, grid: &'a Grid, probabilities: Option<&'a HashMap<WorldPos, f32>>) -> impl Iterator<Item = (WorldPos, [i32; 3], f32, Vec4)> + 'a {
+        let Voxel { width, height, depth } = self.voxel;
+        (0..depth as i32)
+            .flat_map(move |z| (0..height as i32).flat_map(move |y| (0..width as i32).map(move |x| [x, y, z])))
+            .filter(|v| self.is_shown(*v))
+            .filter_map(move |v| {
+                let pos = self.voxel.cell(v)?;
+                let cell = grid.get_cell(pos)?;
+                let (size, color) = match (cell.revealed, cell.contents) {
+                    (false, _) if cell.is_flagged() => (CUBE_SIZE, flag_color(cell.flag)),
+                    (false, _) => match probabilities.and_then(|p| p.get(&pos)) {
+                        Some(p) => (CUBE_SIZE, Vec4::new(0.3 + 0.6 * p, 0.9 - 0.6 * p, 0.3, 1.0)),
+                        None => (CUBE_SIZE, Vec4::new(0.55, 0.6, 0.7, 1.0)),
+                    },
+                    (true, CellContents::Mine(weight)) => (CUBE_SIZE, mine_color(weight)),
+                    (true, CellContents::Empty(0)) => return None,
+                    (true, CellContents::Empty(_)) => (NUMBER_SIZE, Vec4::new(0.9, 0.9, 0.9, 1.0)),
+                };
+                Some((pos, v, size, color))
+            })
+    }
+
+    /// Cell of the cube nearest to the camera under the point `cursor` of the screen.
+    pub fn pick(&self, grid: &Grid, cursor: Vec2) -> Option<WorldPos> {
+        let (origin, direction) = self.camera.ray(cursor);
+        self.cubes(grid, None)
+            .filter_map(|(pos, v, size, _)| AABB::from_size(self.center(v), size).ray_hit(origin, direction).map(|t| (t, pos)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, pos)| pos)
+    }
+
+    /// Draws the cubes, lighter under the cursor, then writes the numbers over them
+    /// and the layers shown in a corner. Leaves the renderer in 2D.
+    pub fn draw(&self, grid: &Grid, probabilities: Option<&HashMap<WorldPos, f32>>, hovered: Option<WorldPos>, renderer: &mut Renderer) {
+        renderer.begin_3d(self.camera.view());
+        renderer.set_model_matrix(&Mat4::identity());
+        for (pos, v, size, color) in self.cubes(grid, probabilities) {
+            let color = if Some(pos) == hovered { color * 0.5 + Vec4::new(0.5, 0.5, 0.5, 0.5) } else { color };
+            renderer.push_cube_at(self.center(v), size, color);
+        }
+
+        renderer.begin_2d();
+        renderer.default_texture();
+        // Farthest first, so that nearer numbers are written over them
+        let eye = self.camera.eye().vec3();
+        let mut numbers: Vec<(f32, Vec3, i32)> = self.cubes(grid, None)
+            .filter_map(|(pos, v, _, _)| match grid.get_cell(pos)?.contents {
+                CellContents::Empty(nb) if nb != 0 => Some(((self.center(v) - eye).magnitude(), self.center(v), nb)),
+                _ => None,
+            })
+            .collect();
+        numbers.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let pixels_per_unit = WINDOW_SIZE.1 as f32 / 2.0 / (std::f32::consts::PI / 6.0).tan();
+        for (distance, center, nb) in numbers {
+            let Some(screen) = self.camera.project(center) else {
+                continue;
+            };
+            let text = nb.to_string();
+            let scale = (pixels_per_unit / distance * 0.5 / 5.0).floor().max(1.0);
+            let size = text_size(&text, scale);
+            let pos = screen - size / 2.0;
+            renderer.push_2d_quad(pos.x - scale, pos.y - scale, size.x + 2.0 * scale, size.y + 2.0 * scale, Vec4::new(0.0, 0.0, 0.0, 0.7));
+            draw_text(renderer, &text, pos, scale, number_color(nb));
+        }
+        self.draw_layers(renderer);
+    }
+
+    /// Writes the axis layers are peeled along, and the first layer shown or the only one.
+    fn draw_layers(&self, renderer: &mut Renderer) {
+        let axis = ["x", "y", "z"][self.axis];
+        let text = if self.slice {
+            format!("{} layer {}/{}", axis, self.peel + 1, self.layers())
+        } else {
+            format!("{} layers {}-{}/{}", axis, self.peel + 1, self.layers(), self.layers())
+        };
+        let scale = 3.0;
+        let size = text_size(&text, scale);
+        let pos = Vec2::new(16.0, 16.0);
+        renderer.push_2d_quad(pos.x - scale, pos.y - scale, size.x + 2.0 * scale, size.y + 2.0 * scale, Vec4::new(0.0, 0.0, 0.0, 0.8));
+        draw_text(renderer, &text, pos, scale, Vec4::new(1.0, 1.0, 1.0, 1.0));
+    }
+}
+
+/// Flags are red, darker for more mines, and blue for negative ones.
+fn flag_color(flag: i32) -> Vec4 {
+    match flag {
+        2 => Vec4::new(0.7, 0.1, 0.1, 1.0),
+        3 => Vec4::new(0.45, 0.05, 0.05, 1.0),
+        -1 => Vec4::new(0.2, 0.3, 0.9, 1.0),
+        _ => Vec4::new(0.95, 0.2, 0.2, 1.0),
+    }
+}
+
+fn mine_color(weight: i32) -> Vec4 {
+    match weight {
+        -1 => Vec4::new(0.1, 0.1, 0.4, 1.0),
+        _ => Vec4::new(0.1, 0.1, 0.1, 1.0),
+    }
+}
+
+fn number_color(nb: i32) -> Vec4 {
+    match nb {
+        1 => Vec4::new(0.4, 0.6, 1.0, 1.0),
+        2 => Vec4::new(0.4, 0.9, 0.4, 1.0),
+        3 => Vec4::new(1.0, 0.4, 0.4, 1.0),
+        n if n < 0 => Vec4::new(0.6, 0.6, 1.0, 1.0),
+        _ => Vec4::new(1.0, 0.8, 0.3, 1.0),
+    }
+}